@@ -0,0 +1,21 @@
+//! Lists the versions of the embedded database migrations, used to report pending migrations
+
+use std::{env, fs, path::Path};
+
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+
+    let mut versions: Vec<String> = fs::read_dir("migrations")
+        .expect("Cannot read migrations directory")
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().join("up.sql").is_file())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            name.split('_').next().map(|version| version.replace('-', ""))
+        })
+        .collect();
+    versions.sort();
+
+    let out = Path::new(&env::var("OUT_DIR").expect("OUT_DIR is not set")).join("migrations.rs");
+    fs::write(out, format!("&{:?}\n", versions)).expect("Cannot write migrations list");
+}
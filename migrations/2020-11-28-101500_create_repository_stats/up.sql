@@ -0,0 +1,12 @@
+CREATE TABLE `repository_stats` (
+    `id` VARCHAR(36) NOT NULL,
+    `repo` VARCHAR(255) NOT NULL,
+    `stars` INT NOT NULL,
+    `open_issues` INT NOT NULL,
+    `pushed_at` DATETIME NULL,
+    `license` VARCHAR(255) NULL,
+    `archived` BOOLEAN NOT NULL,
+    `created_at` DATETIME NOT NULL,
+    PRIMARY KEY (id),
+    INDEX idx_repo_created_at (repo, created_at)
+);
@@ -15,6 +15,112 @@ pub struct Config {
     pub database_url: String,
     pub github_api_username: String,
     pub github_api_token: String,
+    #[serde(default = "default_admin_server_url")]
+    pub admin_server_url: String,
+    #[serde(default)]
+    pub admin_server_port: String,
+    #[serde(default)]
+    pub admin_basic_auth: String,
+    #[serde(default)]
+    pub admin_allowed_ips: String,
+    #[serde(default = "default_health_check_timeout")]
+    pub health_check_timeout: u64,
+    #[serde(default = "default_tracing_exporter")]
+    pub tracing_exporter: String,
+    #[serde(default = "default_tracing_otlp_endpoint")]
+    pub tracing_otlp_endpoint: String,
+    #[serde(default = "default_tracing_service_name")]
+    pub tracing_service_name: String,
+    #[serde(default = "default_compression_min_size")]
+    pub compression_min_size: usize,
+    #[serde(default = "default_access_log_slow_threshold")]
+    pub access_log_slow_threshold: u64,
+    #[serde(default)]
+    pub access_log_slow_routes: String,
+    #[serde(default = "default_ws_heartbeat_interval")]
+    pub ws_heartbeat_interval: u64,
+    #[serde(default = "default_ws_client_timeout")]
+    pub ws_client_timeout: u64,
+    #[serde(default = "default_ws_max_frame_size")]
+    pub ws_max_frame_size: usize,
+    #[serde(default = "default_ws_max_message_size")]
+    pub ws_max_message_size: usize,
+    #[serde(default = "default_ws_outbound_queue_size")]
+    pub ws_outbound_queue_size: usize,
+    #[serde(default = "default_ws_outbound_queue_policy")]
+    pub ws_outbound_queue_policy: String,
+    #[serde(default = "default_ws_rate_limit")]
+    pub ws_rate_limit: u32,
+}
+
+/// Operational endpoints are only reachable locally by default when they have their own listener
+fn default_admin_server_url() -> String {
+    "127.0.0.1".to_owned()
+}
+
+/// Default maximum duration of each readiness check (in milliseconds)
+fn default_health_check_timeout() -> u64 {
+    1000
+}
+
+/// Tracing is disabled by default
+fn default_tracing_exporter() -> String {
+    "none".to_owned()
+}
+
+/// Default OpenTelemetry collector endpoint (OTLP/gRPC)
+fn default_tracing_otlp_endpoint() -> String {
+    "http://localhost:4317".to_owned()
+}
+
+/// Default name of the service in traces
+fn default_tracing_service_name() -> String {
+    "test-actix".to_owned()
+}
+
+/// Default minimum size of a response body to compress (in bytes)
+fn default_compression_min_size() -> usize {
+    1024
+}
+
+/// Default slow request threshold (in milliseconds)
+fn default_access_log_slow_threshold() -> u64 {
+    1000
+}
+
+/// Default interval between two WebSocket pings (in seconds)
+fn default_ws_heartbeat_interval() -> u64 {
+    5
+}
+
+/// Default WebSocket idle timeout (in seconds)
+fn default_ws_client_timeout() -> u64 {
+    10
+}
+
+/// Default maximum size of a WebSocket frame payload (in bytes)
+fn default_ws_max_frame_size() -> usize {
+    64 * 1024
+}
+
+/// Default maximum size of a WebSocket message reassembled from continuation frames (in bytes)
+fn default_ws_max_message_size() -> usize {
+    1024 * 1024
+}
+
+/// Default number of messages waiting to be sent to a WebSocket client
+fn default_ws_outbound_queue_size() -> usize {
+    256
+}
+
+/// Default policy applied when the WebSocket outbound queue is full
+fn default_ws_outbound_queue_policy() -> String {
+    "disconnect".to_owned()
+}
+
+/// Default maximum number of messages per second received from a WebSocket client
+fn default_ws_rate_limit() -> u32 {
+    20
 }
 
 impl Config {
@@ -3,26 +3,59 @@
 pub mod schema;
 
 use crate::errors::AppError;
-use actix_web::web;
+use crate::metrics::Metrics;
+use actix_web::{error::BlockingError, web};
 use color_eyre::Result;
 use diesel::mysql::MysqlConnection;
-use diesel::r2d2::{ConnectionManager, Pool, PoolError, PooledConnection};
+use diesel::r2d2::event::{CheckoutEvent, TimeoutEvent};
+use diesel::r2d2::{ConnectionManager, HandleEvent, Pool, PoolError, PooledConnection};
+use diesel::QueryResult;
+use diesel_migrations::MigrationConnection;
+use prometheus::{Histogram, IntCounter};
+use std::time::Instant;
 
 // Embed and run migrations
 embed_migrations!();
 
+/// Versions of the embedded migrations (listed by `build.rs`)
+const MIGRATIONS: &[&str] = include!(concat!(env!("OUT_DIR"), "/migrations.rs"));
+
 pub type MysqlPool = Pool<ConnectionManager<MysqlConnection>>;
 pub type MySqlPooledConnection = PooledConnection<ConnectionManager<MysqlConnection>>;
 
 pub fn mysql_pool_handler(pool: web::Data<MysqlPool>) -> Result<MySqlPooledConnection, AppError> {
-    pool.get().map_err(|_| AppError::InternalError {
+    pool.get().map_err(|e| AppError::ServiceUnavailable {
         message: "Database error".to_owned(),
+        source: Some(e.into()),
     })
 }
 
-pub fn init(database_url: &str) -> Result<MysqlPool, PoolError> {
+/// Records connection checkouts in the application metrics
+#[derive(Debug)]
+struct PoolEvents {
+    wait: Histogram,
+    timeouts: IntCounter,
+}
+
+impl HandleEvent for PoolEvents {
+    fn handle_checkout(&self, event: CheckoutEvent) {
+        self.wait.observe(event.duration().as_secs_f64());
+    }
+
+    fn handle_timeout(&self, event: TimeoutEvent) {
+        self.wait.observe(event.timeout().as_secs_f64());
+        self.timeouts.inc();
+    }
+}
+
+pub fn init(database_url: &str, metrics: &Metrics) -> Result<MysqlPool, PoolError> {
     let manager = ConnectionManager::<MysqlConnection>::new(database_url);
-    let pool = Pool::builder().build(manager)?;
+    let pool = Pool::builder()
+        .event_handler(Box::new(PoolEvents {
+            wait: metrics.db_pool_wait.clone(),
+            timeouts: metrics.db_pool_timeouts.clone(),
+        }))
+        .build(manager)?;
 
     // Run embedded database migrations
     embedded_migrations::run_with_output(
@@ -33,3 +66,45 @@ pub fn init(database_url: &str) -> Result<MysqlPool, PoolError> {
 
     Ok(pool)
 }
+
+/// Returns the versions of the embedded migrations which have not been run
+pub fn pending_migrations(connection: &MysqlConnection) -> QueryResult<Vec<&'static str>> {
+    let run = connection.previously_run_migration_versions()?;
+    Ok(MIGRATIONS
+        .iter()
+        .copied()
+        .filter(|version| !run.contains(*version))
+        .collect())
+}
+
+/// Runs a blocking database call in the thread pool, within a child span of the current one
+///
+/// The time spent waiting for a thread is recorded in `metrics`.
+pub async fn block<F, I, E>(metrics: &Metrics, operation: &'static str, f: F) -> Result<I, BlockingError<E>>
+where
+    F: FnOnce() -> Result<I, E> + Send + 'static,
+    I: Send + 'static,
+    E: std::fmt::Debug + Send + 'static,
+{
+    let span = tracing::info_span!(
+        "database",
+        otel.name = operation,
+        otel.kind = "client",
+        db.system = "mysql",
+    );
+    let queue = metrics.db_block_queue.clone();
+    let submitted_at = Instant::now();
+    web::block(move || {
+        queue.observe(submitted_at.elapsed().as_secs_f64());
+        span.in_scope(f)
+    })
+    .await
+}
+
+/// Pool which never opens a connection until one is checked out
+#[cfg(test)]
+pub fn lazy_pool() -> MysqlPool {
+    Pool::builder()
+        .min_idle(Some(0))
+        .build_unchecked(ConnectionManager::new("mysql://localhost/test"))
+}
@@ -1,3 +1,16 @@
+table! {
+    repository_stats (id) {
+        id -> Varchar,
+        repo -> Varchar,
+        stars -> Integer,
+        open_issues -> Integer,
+        pushed_at -> Nullable<Datetime>,
+        license -> Nullable<Varchar>,
+        archived -> Bool,
+        created_at -> Datetime,
+    }
+}
+
 table! {
     users (id) {
         id -> Varchar,
@@ -7,3 +20,5 @@ table! {
         password -> Varchar,
     }
 }
+
+allow_tables_to_appear_in_same_query!(repository_stats, users,);
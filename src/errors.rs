@@ -1,10 +1,14 @@
 //! Custom error module
 
+use crate::negotiation::negotiate;
 use actix_http::ResponseBuilder;
-use actix_web::{error, http::header, http::StatusCode, HttpResponse};
-use derive_more::{Display, Error};
+use actix_web::error::BlockingError;
+use actix_web::{error, http::header, http::StatusCode, HttpRequest, HttpResponse};
+use askama_actix::Template;
+use derive_more::Display;
 use diesel::result::{DatabaseErrorKind, Error as DBError};
 use serde::Serialize;
+use std::error::Error as StdError;
 
 /// Represents the custom error message
 #[derive(Serialize)]
@@ -12,41 +16,265 @@ pub struct AppErrorMessage {
     pub code: u16,
     pub error: String,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
+/// Represents an RFC 7807 problem details message
+#[derive(Serialize)]
+pub struct ProblemDetails {
+    /// URI reference identifying the problem type
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    /// Path of the request which has produced the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// Stable error code
+    pub code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl ProblemDetails {
+    pub fn new(status: StatusCode, code: &'static str, detail: String) -> Self {
+        Self {
+            kind: format!("/errors/{}", code),
+            title: status.canonical_reason().unwrap_or("Error").to_owned(),
+            status: status.as_u16(),
+            detail,
+            instance: None,
+            code,
+            request_id: None,
+        }
+    }
+}
+
+/// Format of error responses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    /// `AppErrorMessage` body
+    Legacy,
+    /// `application/problem+json` body
+    Problem,
+    /// HTML page
+    Html,
+}
+
+impl ErrorFormat {
+    /// Chooses the preferred format of the `Accept` header, ties going to the first media type
+    ///
+    /// Problem details are only returned to clients accepting `application/problem+json`.
+    pub fn from_request(req: &HttpRequest) -> Self {
+        negotiate(
+            req,
+            &[
+                ("application/problem+json", Self::Problem),
+                ("application/json", Self::Legacy),
+                ("text/html", Self::Html),
+            ],
+        )
+        .unwrap_or(Self::Legacy)
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Legacy => "application/json; charset=utf-8",
+            Self::Problem => "application/problem+json",
+            Self::Html => "text/html; charset=utf-8",
+        }
+    }
+}
+
+/// Returns the stable error code of an HTTP status
+pub fn status_code_name(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "bad_request",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::REQUEST_TIMEOUT => "request_timeout",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNPROCESSABLE_ENTITY => "unprocessable_entity",
+        StatusCode::TOO_MANY_REQUESTS => "too_many_requests",
+        StatusCode::BAD_GATEWAY => "bad_gateway",
+        StatusCode::SERVICE_UNAVAILABLE => "service_unavailable",
+        StatusCode::GATEWAY_TIMEOUT => "gateway_timeout",
+        s if s.is_client_error() => "client_error",
+        _ => "internal_error",
+    }
+}
+
+/// HTML error page
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorTemplate<'a> {
+    status: u16,
+    title: &'a str,
+    message: &'a str,
+    request_id: Option<String>,
+}
+
+/// Serializes an error in the given format and returns its content type and body
+pub fn error_body(
+    format: ErrorFormat,
+    status: StatusCode,
+    code: &'static str,
+    error: String,
+    message: String,
+    instance: Option<&str>,
+    request_id: Option<String>,
+) -> (&'static str, String) {
+    let body = match format {
+        ErrorFormat::Legacy => serde_json::to_string(&AppErrorMessage {
+            code: status.as_u16(),
+            error,
+            message,
+            request_id,
+        })
+        .map_err(|e| e.to_string()),
+        ErrorFormat::Problem => {
+            let mut problem = ProblemDetails::new(status, code, message);
+            problem.instance = instance.map(ToOwned::to_owned);
+            problem.request_id = request_id;
+            serde_json::to_string(&problem).map_err(|e| e.to_string())
+        }
+        ErrorFormat::Html => ErrorTemplate {
+            status: status.as_u16(),
+            title: status.canonical_reason().unwrap_or("Error"),
+            message: &message,
+            request_id,
+        }
+        .render()
+        .map_err(|e| e.to_string()),
+    };
+    (format.content_type(), body.unwrap_or_default())
+}
+
+/// Source of an error, logged but never returned to clients
+pub type ErrorSource = Box<dyn StdError + Send + Sync>;
+
 /// Defines available errors
-#[derive(Debug, Display, Error)]
+#[derive(Debug, Display)]
 pub enum AppError {
     #[display(fmt = "{}", message)]
-    InternalError { message: String },
+    InternalError {
+        message: String,
+        source: Option<ErrorSource>,
+    },
     #[display(fmt = "{}", message)]
     BadRequest { message: String },
     #[display(fmt = "{}", message)]
     NotFound { message: String },
     #[display(fmt = "Unauthorized")]
     Unauthorized,
+    #[display(fmt = "{}", message)]
+    Forbidden { message: String },
+    #[display(fmt = "{}", message)]
+    Conflict {
+        message: String,
+        source: Option<ErrorSource>,
+    },
+    #[display(fmt = "{}", message)]
+    UnprocessableEntity { message: String },
+    #[display(fmt = "{}", message)]
+    TooManyRequests { message: String },
+    #[display(fmt = "{}", message)]
+    ServiceUnavailable {
+        message: String,
+        source: Option<ErrorSource>,
+    },
 }
 
 impl AppError {
+    /// Internal error caused by `source`
+    pub fn internal<S: Into<ErrorSource>>(message: &str, source: S) -> Self {
+        Self::InternalError {
+            message: message.to_owned(),
+            source: Some(source.into()),
+        }
+    }
+
+    /// Logs server errors, and errors with a source, at error level
+    pub fn log(&self) {
+        if let Some(message) = self.log_message() {
+            error!("{}", message);
+        }
+    }
+
+    fn log_message(&self) -> Option<String> {
+        match self.source() {
+            Some(source) => Some(format!("{}: {}", self, source)),
+            None if error::ResponseError::status_code(self).is_server_error() => Some(self.to_string()),
+            None => None,
+        }
+    }
+
     pub fn name(&self) -> String {
         match self {
             Self::NotFound { message: m } => m.to_owned(),
             Self::BadRequest { message: m } => m.to_owned(),
             Self::Unauthorized => "Unauthorized".to_owned(),
-            Self::InternalError { message: m } => m.to_owned(),
+            Self::InternalError { message: m, .. } => m.to_owned(),
+            Self::Forbidden { message: m } => m.to_owned(),
+            Self::Conflict { message: m, .. } => m.to_owned(),
+            Self::UnprocessableEntity { message: m } => m.to_owned(),
+            Self::TooManyRequests { message: m } => m.to_owned(),
+            Self::ServiceUnavailable { message: m, .. } => m.to_owned(),
+        }
+    }
+
+    /// Stable error code, part of the API contract
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InternalError { .. } => "internal_error",
+            Self::BadRequest { .. } => "bad_request",
+            Self::NotFound { .. } => "not_found",
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden { .. } => "forbidden",
+            Self::Conflict { .. } => "conflict",
+            Self::UnprocessableEntity { .. } => "unprocessable_entity",
+            Self::TooManyRequests { .. } => "too_many_requests",
+            Self::ServiceUnavailable { .. } => "service_unavailable",
+        }
+    }
+}
+
+impl StdError for AppError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::InternalError { source, .. }
+            | Self::Conflict { source, .. }
+            | Self::ServiceUnavailable { source, .. } => source
+                .as_ref()
+                .map(|source| source.as_ref() as &(dyn StdError + 'static)),
+            _ => None,
         }
     }
 }
 
 impl error::ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
+        self.log();
+
+        // The request is not available here, the `ErrorRenderer` middleware
+        // negotiates the format and adds the instance afterwards
+        let (content_type, body) = error_body(
+            ErrorFormat::Legacy,
+            self.status_code(),
+            self.code(),
+            self.name(),
+            self.to_string(),
+            None,
+            None,
+        );
         ResponseBuilder::new(self.status_code())
-            .set_header(header::CONTENT_TYPE, "application/json; charset=utf-8")
-            .json(AppErrorMessage {
-                code: self.status_code().as_u16(),
-                error: self.name(),
-                message: self.to_string(),
-            })
+            .set_header(header::CONTENT_TYPE, content_type)
+            .body(body)
     }
 
     fn status_code(&self) -> StatusCode {
@@ -55,6 +283,11 @@ impl error::ResponseError for AppError {
             AppError::BadRequest { .. } => StatusCode::BAD_REQUEST,
             AppError::Unauthorized => StatusCode::UNAUTHORIZED,
             AppError::NotFound { .. } => StatusCode::NOT_FOUND,
+            AppError::Forbidden { .. } => StatusCode::FORBIDDEN,
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            AppError::UnprocessableEntity { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::ServiceUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
 }
@@ -62,18 +295,81 @@ impl error::ResponseError for AppError {
 impl From<DBError> for AppError {
     fn from(error: DBError) -> AppError {
         match error {
-            DBError::DatabaseError(kind, info) => {
-                if let DatabaseErrorKind::UniqueViolation = kind {
-                    let message = info.details().unwrap_or_else(|| info.message()).to_string();
-                    return AppError::BadRequest { message };
-                }
-                AppError::InternalError {
-                    message: "Internal Server Error".to_owned(),
+            DBError::NotFound => AppError::NotFound {
+                message: "Resource not found".to_owned(),
+            },
+            DBError::DatabaseError(DatabaseErrorKind::UniqueViolation, ref info) => {
+                let message = info.details().unwrap_or_else(|| info.message()).to_string();
+                AppError::Conflict {
+                    message,
+                    source: Some(error.into()),
                 }
             }
-            _ => AppError::InternalError {
-                message: "Internal Server Error".to_owned(),
+            DBError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, ref info) => AppError::UnprocessableEntity {
+                message: info.details().unwrap_or_else(|| info.message()).to_string(),
             },
+            _ => AppError::internal("Internal Server Error", error),
         }
     }
 }
+
+impl From<BlockingError<DBError>> for AppError {
+    fn from(error: BlockingError<DBError>) -> AppError {
+        match error {
+            BlockingError::Error(error) => error.into(),
+            BlockingError::Canceled => AppError::ServiceUnavailable {
+                message: "Database thread pool is gone".to_owned(),
+                source: Some(error.into()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::ResponseError;
+
+    #[test]
+    fn test_from_db_error() {
+        let error = DBError::DatabaseError(
+            DatabaseErrorKind::UniqueViolation,
+            Box::new("Duplicate entry 'john.doe@test.com' for key 'email'".to_owned()),
+        );
+        let error = AppError::from(error);
+        assert_eq!(error.status_code(), StatusCode::CONFLICT);
+        assert_eq!(error.code(), "conflict");
+        assert_eq!(error.to_string(), "Duplicate entry 'john.doe@test.com' for key 'email'");
+        assert!(error.source().is_some());
+
+        let error = AppError::from(DBError::NotFound);
+        assert_eq!(error.status_code(), StatusCode::NOT_FOUND);
+
+        let error = AppError::from(DBError::RollbackTransaction);
+        assert_eq!(error.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn test_log_message() {
+        let error = AppError::from(DBError::RollbackTransaction);
+        assert_eq!(
+            error.log_message().as_deref(),
+            Some("Internal Server Error: The current transaction was aborted")
+        );
+
+        // Server errors without source
+        let error = AppError::InternalError {
+            message: "Cannot render template".to_owned(),
+            source: None,
+        };
+        assert_eq!(error.log_message().as_deref(), Some("Cannot render template"));
+        let error = AppError::ServiceUnavailable {
+            message: "Database is unavailable".to_owned(),
+            source: None,
+        };
+        assert_eq!(error.log_message().as_deref(), Some("Database is unavailable"));
+
+        assert_eq!(AppError::Unauthorized.log_message(), None);
+    }
+}
@@ -0,0 +1,38 @@
+//! Administration handlers module
+
+use crate::errors::AppError;
+use crate::logger::{self, Filters};
+use actix_web::{web, HttpResponse};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+
+/// Log level filters, as `SERVER_LOG_LEVEL`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogLevels {
+    pub filters: String,
+}
+
+// Route: GET "/admin/log-levels"
+// curl http://localhost:8089/admin/log-levels
+pub async fn log_levels() -> HttpResponse {
+    HttpResponse::Ok().json(LogLevels {
+        filters: logger::filters().to_string(),
+    })
+}
+
+// Route: PUT "/admin/log-levels"
+// curl -X PUT http://localhost:8089/admin/log-levels \
+// -H "Content-Type: application/json" -d '{"filters": "info,actix_web=warn,test_actix=debug"}'
+pub async fn set_log_levels(form: web::Json<LogLevels>) -> Result<HttpResponse, AppError> {
+    let filters: Filters = form
+        .filters
+        .parse()
+        .map_err(|message| AppError::UnprocessableEntity { message })?;
+
+    info!("Log levels changed to {}", filters);
+    logger::set_filters(filters);
+
+    Ok(HttpResponse::Ok().json(LogLevels {
+        filters: logger::filters().to_string(),
+    }))
+}
@@ -0,0 +1,116 @@
+//! Version drift handlers module
+
+use crate::db::MysqlPool;
+use crate::errors::AppError;
+use crate::handlers::releases::get_releases;
+use crate::models::drift::{Dependency, DriftReport};
+use crate::AppState;
+use actix_multipart::Multipart;
+use actix_web::{web, HttpResponse};
+use askama_actix::{Template, TemplateIntoResponse};
+use color_eyre::Result;
+use futures::StreamExt;
+
+/// Maximum size of an uploaded file (in bytes)
+const MAX_FILE_SIZE: usize = 5 * 1024 * 1024;
+
+#[derive(Template)]
+#[template(path = "drift.html")]
+struct DriftTemplate<'a> {
+    report: Option<&'a DriftReport>,
+}
+
+// Route: POST "/github/drift"
+// curl -F cargo_lock=@Cargo.lock -F go_mod=@go.mod -F composer_lock=@composer.lock http://127.0.0.1:8089/github/drift
+pub async fn report(
+    data: web::Data<AppState>,
+    pool: web::Data<MysqlPool>,
+    payload: Multipart,
+) -> Result<HttpResponse, AppError> {
+    let dependencies = read_dependencies(payload).await?;
+    let (releases, _) = get_releases(&data, &pool).await;
+
+    Ok(HttpResponse::Ok().json(DriftReport::new(dependencies, &releases)))
+}
+
+// Route: GET "/github-drift-page"
+pub async fn page() -> Result<HttpResponse, AppError> {
+    render_page(None)
+}
+
+// Route: POST "/github-drift-page"
+pub async fn page_report(
+    data: web::Data<AppState>,
+    pool: web::Data<MysqlPool>,
+    payload: Multipart,
+) -> Result<HttpResponse, AppError> {
+    let dependencies = read_dependencies(payload).await?;
+    let (releases, _) = get_releases(&data, &pool).await;
+
+    render_page(Some(&DriftReport::new(dependencies, &releases)))
+}
+
+fn render_page(report: Option<&DriftReport>) -> Result<HttpResponse, AppError> {
+    DriftTemplate { report }
+        .into_response()
+        .map_err(|e| AppError::internal("Failed to load DriftTemplate.", e.to_string()))
+}
+
+/// Reads dependencies from the multipart form files:
+/// `cargo_lock` (Cargo.lock), `go_mod` (go.mod) and `composer_lock` (composer.lock)
+async fn read_dependencies(mut payload: Multipart) -> Result<Vec<Dependency>, AppError> {
+    let mut dependencies = Vec::new();
+    let mut files_count = 0;
+
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|e| AppError::BadRequest { message: e.to_string() })?;
+        let name = field
+            .content_disposition()
+            .and_then(|cd| cd.get_name().map(String::from))
+            .unwrap_or_default();
+
+        let mut content = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| AppError::BadRequest { message: e.to_string() })?;
+            if content.len() + chunk.len() > MAX_FILE_SIZE {
+                return Err(AppError::BadRequest {
+                    message: format!("File {} is too large", name),
+                });
+            }
+            content.extend_from_slice(&chunk);
+        }
+
+        // Empty file inputs are sent by HTML forms
+        if content.is_empty() {
+            continue;
+        }
+        let content = String::from_utf8(content).map_err(|_| AppError::BadRequest {
+            message: format!("File {} is not a valid UTF-8 file", name),
+        })?;
+
+        match name.as_str() {
+            "cargo_lock" => {
+                dependencies.extend(Dependency::from_cargo_lock(&content).map_err(|e| AppError::BadRequest {
+                    message: format!("Invalid Cargo.lock file: {}", e),
+                })?)
+            }
+            "go_mod" => dependencies.extend(Dependency::from_go_mod(&content)),
+            "composer_lock" => {
+                dependencies.extend(
+                    Dependency::from_composer_lock(&content).map_err(|e| AppError::BadRequest {
+                        message: format!("Invalid composer.lock file: {}", e),
+                    })?,
+                )
+            }
+            _ => continue,
+        }
+        files_count += 1;
+    }
+
+    if files_count == 0 {
+        return Err(AppError::BadRequest {
+            message: "No Cargo.lock, go.mod or composer.lock file uploaded".to_owned(),
+        });
+    }
+    Ok(dependencies)
+}
@@ -1,85 +1,47 @@
 //! Errors handlers module
 
-use actix_web::middleware::errhandlers::ErrorHandlerResponse;
-use actix_web::{body::Body, body::ResponseBody, dev, http};
-use actix_web::{error, http::StatusCode};
+use crate::errors::{error_body, status_code_name, AppError, ErrorFormat};
+use crate::middlewares::request_id;
+use actix_web::{body::Body, body::ResponseBody, dev, http, HttpRequest, HttpResponse};
 use color_eyre::Result;
-use serde_json::json;
 
-fn render_error<B>(
-    mut res: dev::ServiceResponse<B>,
-    code: u16,
-    error: String,
-    message: String,
-) -> ErrorHandlerResponse<B> {
-    let err = json!(crate::errors::AppErrorMessage { code, error, message });
-
-    res.request();
-    res.headers_mut().insert(
-        http::header::CONTENT_TYPE,
-        http::HeaderValue::from_static("application/json"),
+/// Renders an error response in the format accepted by the client
+///
+/// `AppError` errors keep their code and message. Messages of other errors are only
+/// returned for 4xx statuses (extractors errors for example).
+pub fn render<B>(mut res: dev::ServiceResponse<B>) -> dev::ServiceResponse<B> {
+    let status = res.status();
+    let reason = status.canonical_reason().unwrap_or("Error");
+    let format = ErrorFormat::from_request(res.request());
+    let (code, error, message) = match res.response().error() {
+        Some(e) => match e.as_error::<AppError>() {
+            Some(e) => (e.code(), e.name(), e.to_string()),
+            None if status.is_client_error() => (status_code_name(status), reason.to_owned(), e.to_string()),
+            None => (status_code_name(status), reason.to_owned(), reason.to_owned()),
+        },
+        None => (status_code_name(status), reason.to_owned(), reason.to_owned()),
+    };
+    let request_id = request_id::Id::get(res.request());
+    let (content_type, body) = error_body(
+        format,
+        status,
+        code,
+        error,
+        message,
+        Some(res.request().path()),
+        request_id,
     );
-    res = res.map_body(|_, _| ResponseBody::Body(Body::from(err)).into_body());
-
-    ErrorHandlerResponse::Response(res)
-}
-
-/// Render 401 error
-pub fn render_401<B>(res: dev::ServiceResponse<B>) -> Result<ErrorHandlerResponse<B>, error::Error> {
-    Ok(render_error(
-        res,
-        StatusCode::UNAUTHORIZED.as_u16(),
-        String::from("Unauthorized"),
-        "Unauthorized".to_owned(),
-    ))
-}
-
-/// Render 403 error
-pub fn render_403<B>(res: dev::ServiceResponse<B>) -> Result<ErrorHandlerResponse<B>, error::Error> {
-    Ok(render_error(
-        res,
-        StatusCode::FORBIDDEN.as_u16(),
-        String::from("Forbidden"),
-        "Forbidden".to_owned(),
-    ))
-}
-
-/// Render 408 error
-pub fn render_408<B>(res: dev::ServiceResponse<B>) -> Result<ErrorHandlerResponse<B>, error::Error> {
-    Ok(render_error(
-        res,
-        StatusCode::REQUEST_TIMEOUT.as_u16(),
-        String::from("Request Time-out"),
-        "Request Time-out".to_owned(),
-    ))
-}
-
-/// Render 502 error
-pub fn render_502<B>(res: dev::ServiceResponse<B>) -> Result<ErrorHandlerResponse<B>, error::Error> {
-    Ok(render_error(
-        res,
-        StatusCode::BAD_GATEWAY.as_u16(),
-        String::from("Bad Gateway"),
-        "Bad Gateway".to_owned(),
-    ))
-}
 
-/// Render 503 error
-pub fn render_503<B>(res: dev::ServiceResponse<B>) -> Result<ErrorHandlerResponse<B>, error::Error> {
-    Ok(render_error(
-        res,
-        StatusCode::SERVICE_UNAVAILABLE.as_u16(),
-        String::from("Service Unavailable"),
-        "Service Unavailable".to_owned(),
-    ))
+    res.headers_mut()
+        .insert(http::header::CONTENT_TYPE, http::HeaderValue::from_static(content_type));
+    res.headers_mut().remove(http::header::CONTENT_LENGTH);
+    res.map_body(|_, _| ResponseBody::Body(Body::from(body)).into_body())
 }
 
-/// Render 504 error
-pub fn render_504<B>(res: dev::ServiceResponse<B>) -> Result<ErrorHandlerResponse<B>, error::Error> {
-    Ok(render_error(
-        res,
-        StatusCode::GATEWAY_TIMEOUT.as_u16(),
-        String::from("Gateway Time-out"),
-        "Gateway Time-out".to_owned(),
-    ))
+// Route: default service
+// curl -i http://localhost:8089/unknown
+pub async fn not_found(req: HttpRequest) -> Result<HttpResponse, AppError> {
+    Err(AppError::NotFound {
+        message: format!("No route for {} {}", req.method(), req.path()),
+    })
 }
@@ -0,0 +1,37 @@
+//! Server-Sent Events handlers
+
+use crate::errors::AppError;
+use crate::handlers::ws::authenticate;
+use crate::sse::{Broadcaster, Subscribe};
+use crate::AppState;
+use actix::SystemService;
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use color_eyre::Result;
+
+/// Header sent by clients reconnecting to the events stream
+const LAST_EVENT_ID: &str = "Last-Event-ID";
+
+/// Stream application events
+// Route: GET "/events"
+// curl -N http://127.0.0.1:8089/events -H 'Authorization: Bearer <token>' -H 'Last-Event-ID: 1607594400000-12'
+pub async fn stream(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse, AppError> {
+    authenticate(&req, &data.jwt_secret_key)?;
+
+    let last_event_id = req
+        .headers()
+        .get(LAST_EVENT_ID)
+        .and_then(|id| id.to_str().ok())
+        .and_then(|id| id.trim().parse().ok());
+
+    let stream = Broadcaster::from_registry()
+        .send(Subscribe { last_event_id })
+        .await
+        .map_err(|e| AppError::internal("Failed to subscribe to events", e))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .set_header(header::CACHE_CONTROL, "no-cache")
+        // Disables proxy buffering (Nginx)
+        .set_header("X-Accel-Buffering", "no")
+        .streaming(stream))
+}
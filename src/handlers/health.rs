@@ -0,0 +1,192 @@
+//! Health handlers module
+
+use crate::db;
+use crate::db::MysqlPool;
+use crate::metrics::Metrics;
+use crate::models::health::{ComponentHealth, Health, HealthSettings, HealthStatus};
+use crate::models::release::ReleasesCache;
+use crate::AppState;
+use actix_web::{web, HttpResponse};
+use chrono::{Duration, Utc};
+use diesel::RunQueryDsl;
+use futures::Future;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+/// Delay after its expiration from which the releases cache is considered stale
+const RELEASES_CACHE_GRACE_MINUTES: i64 = 5;
+
+// Route: GET "/health/live"
+// curl http://127.0.0.1:8089/health/live
+pub async fn live() -> HttpResponse {
+    HttpResponse::Ok().json(Health::new(BTreeMap::new()))
+}
+
+// Route: GET "/health/ready"
+// curl http://127.0.0.1:8089/health/ready
+pub async fn ready(
+    pool: web::Data<MysqlPool>,
+    data: web::Data<AppState>,
+    settings: Option<web::Data<HealthSettings>>,
+) -> HttpResponse {
+    let settings = settings.map(|s| s.get_ref().clone()).unwrap_or_default();
+
+    let (database, migrations, releases_cache) = futures::join!(
+        check(
+            &settings,
+            true,
+            database(pool.get_ref().clone(), &settings, &data.metrics)
+        ),
+        check(
+            &settings,
+            true,
+            migrations(pool.get_ref().clone(), &settings, &data.metrics)
+        ),
+        check(&settings, false, async { releases_cache(&data.releases) }),
+    );
+
+    let mut components = BTreeMap::new();
+    components.insert("database", database);
+    components.insert("migrations", migrations);
+    components.insert("releases_cache", releases_cache);
+
+    response(Health::new(components))
+}
+
+/// Readiness response, `503 Service Unavailable` if a critical component is down
+fn response(health: Health) -> HttpResponse {
+    match health.status {
+        HealthStatus::Down => HttpResponse::ServiceUnavailable().json(health),
+        _ => HttpResponse::Ok().json(health),
+    }
+}
+
+/// Runs a check within the configured timeout
+async fn check<F>(settings: &HealthSettings, critical: bool, f: F) -> ComponentHealth
+where
+    F: Future<Output = Result<(), String>>,
+{
+    let started_at = Instant::now();
+    let result = actix_rt::time::timeout(settings.timeout, f)
+        .await
+        .unwrap_or_else(|_| Err(format!("timed out after {} ms", settings.timeout.as_millis())));
+    ComponentHealth::new(critical, started_at.elapsed(), result)
+}
+
+/// Checks out a connection and runs a trivial query
+async fn database(pool: MysqlPool, settings: &HealthSettings, metrics: &Metrics) -> Result<(), String> {
+    let timeout = settings.timeout;
+    db::block(metrics, "health.database", move || {
+        let connection = pool.get_timeout(timeout).map_err(|e| e.to_string())?;
+        diesel::sql_query("SELECT 1")
+            .execute(&connection)
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// Checks that all embedded migrations have been run
+async fn migrations(pool: MysqlPool, settings: &HealthSettings, metrics: &Metrics) -> Result<(), String> {
+    let timeout = settings.timeout;
+    let pending = db::block(metrics, "health.migrations", move || {
+        let connection = pool.get_timeout(timeout).map_err(|e| e.to_string())?;
+        db::pending_migrations(&connection).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    match pending.as_slice() {
+        [] => Ok(()),
+        pending => Err(format!("pending migrations: {}", pending.join(", "))),
+    }
+}
+
+/// Checks that releases have been loaded and refreshed recently
+fn releases_cache(releases: &ReleasesCache) -> Result<(), String> {
+    match releases.expired_at() {
+        None => Err("releases have not been loaded".to_owned()),
+        Some(expired_at) if expired_at + Duration::minutes(RELEASES_CACHE_GRACE_MINUTES) < Utc::now() => {
+            Err(format!("releases have expired at {}", expired_at.to_rfc3339()))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middlewares::errors::ErrorRenderer;
+    use crate::models::release::Release;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use std::time::Duration as StdDuration;
+
+    #[test]
+    fn test_releases_cache() {
+        assert!(releases_cache(&ReleasesCache::default()).is_err());
+
+        let releases = vec![Release::fake("actix-web", "Rust", "v3.3.2", "2020-12-01T10:00:00Z")];
+        let cache = ReleasesCache::loaded(releases.clone(), Utc::now() + Duration::minutes(30));
+        assert!(releases_cache(&cache).is_ok());
+
+        // Expired within the grace delay
+        let cache = ReleasesCache::loaded(releases.clone(), Utc::now() - Duration::minutes(2));
+        assert!(releases_cache(&cache).is_ok());
+
+        let cache = ReleasesCache::loaded(releases, Utc::now() - Duration::minutes(10));
+        assert!(releases_cache(&cache)
+            .unwrap_err()
+            .starts_with("releases have expired at "));
+    }
+
+    #[actix_rt::test]
+    async fn test_check_timeout() {
+        let settings = HealthSettings {
+            timeout: StdDuration::from_millis(10),
+        };
+        let component = check(&settings, true, async {
+            actix_rt::time::delay_for(StdDuration::from_secs(1)).await;
+            Ok(())
+        })
+        .await;
+        assert_eq!(component.status, HealthStatus::Down);
+        assert_eq!(component.message.as_deref(), Some("timed out after 10 ms"));
+    }
+
+    #[actix_rt::test]
+    async fn test_unavailable_body() {
+        let mut app = test::init_service(App::new().wrap(ErrorRenderer).route(
+            "/health/ready",
+            web::get().to(|| {
+                let mut components = BTreeMap::new();
+                components.insert(
+                    "database",
+                    ComponentHealth::new(true, StdDuration::from_millis(3), Err("connection refused".to_owned())),
+                );
+                response(Health::new(components))
+            }),
+        ))
+        .await;
+
+        let req = test::TestRequest::get().uri("/health/ready").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "status": "down",
+                "components": {
+                    "database": {
+                        "status": "down",
+                        "critical": true,
+                        "duration_ms": 3,
+                        "message": "connection refused",
+                    },
+                },
+            })
+        );
+    }
+}
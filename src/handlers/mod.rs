@@ -1,15 +1,22 @@
 //! Handlers module
 
+pub mod admin;
+pub mod drift;
 pub mod errors;
+pub mod events;
+pub mod health;
 pub mod releases;
 pub mod users;
 pub mod ws;
 
 use crate::errors::AppError;
 use crate::models;
+use crate::streaming::StreamResponse;
 use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
 use askama_actix::{Template, TemplateIntoResponse};
 use color_eyre::Result;
+use futures::stream;
+use prometheus::{Encoder, Registry, TextEncoder};
 use std::thread;
 
 pub async fn index() -> Result<impl Responder, AppError> {
@@ -28,10 +35,22 @@ pub async fn health_check() -> Result<impl Responder, AppError> {
     Ok(HttpResponse::Ok().finish())
 }
 
+// Route: GET "/metrics"
+// curl http://127.0.0.1:8089/metrics -u admin:password
+pub async fn metrics(registry: web::Data<Registry>) -> Result<HttpResponse, AppError> {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder
+        .encode(&registry.gather(), &mut buffer)
+        .map_err(|e| AppError::internal("Error while encoding metrics", e))?;
+    Ok(HttpResponse::Ok().content_type(encoder.format_type()).body(buffer))
+}
+
 #[get("/internal-error")]
 pub async fn internal_error() -> Result<&'static str, AppError> {
     Err(AppError::InternalError {
         message: "an unexpected error".to_owned(),
+        source: None,
     })
 }
 
@@ -86,15 +105,17 @@ pub async fn big_json() -> Result<web::Json<Vec<models::Task>>, AppError> {
     Ok(web::Json(v))
 }
 
+// JSON array, NDJSON or CSV depending on `Accept` header
+// curl http://127.0.0.1:8089/big-json-stream/1000 -H 'Accept: application/x-ndjson'
 #[get("/big-json-stream/{number}")]
-pub async fn big_json_stream(number: web::Path<u32>) -> HttpResponse {
-    let stream = models::TaskStream {
-        number: *number,
-        next: 0,
-        buf: Default::default(),
-    };
+pub async fn big_json_stream(number: web::Path<u32>) -> StreamResponse<models::Task> {
+    let tasks = (0..*number).map(|id| models::Task {
+        id,
+        name: "Coucou ceci est mon nom",
+        message: String::from("Mon message doit être un peu long pour augmenter la taille"),
+    });
 
-    HttpResponse::Ok().content_type("application/json").streaming(stream)
+    StreamResponse::new(stream::iter(tasks))
 }
 
 #[derive(Template)]
@@ -105,10 +126,7 @@ struct HelloTemplate<'a> {
 
 #[get("/templates")]
 pub async fn templates() -> Result<HttpResponse, AppError> {
-    HelloTemplate { name: "world" }.into_response().map_err(|e| {
-        error!("{}", e);
-        AppError::InternalError {
-            message: "Failed to load HelloTemplate.".to_owned(),
-        }
-    })
+    HelloTemplate { name: "world" }
+        .into_response()
+        .map_err(|e| AppError::internal("Failed to load HelloTemplate.", e.to_string()))
 }
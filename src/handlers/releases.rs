@@ -1,20 +1,51 @@
 //! Github handler module
 
+use crate::db::MysqlPool;
 use crate::errors::AppError;
-use crate::models::release::{Project, Release, PROJECTS_FILE};
+use crate::models::release::{FeedEntry, FeedQuery, Project, Release, ReleasesQuery, ReleasesSort, SortOrder};
 use crate::AppState;
-use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web::dev::HttpResponseBuilder;
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
 use askama_actix::{Template, TemplateIntoResponse};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use color_eyre::Result;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::SystemTime;
 
 #[derive(Template)]
 #[template(path = "github.html", print = "none")]
 struct GithubTemplate<'a> {
-    _releases: &'a Vec<Release>,
+    groups: &'a BTreeMap<String, Vec<Release>>,
+    languages: Vec<String>,
+    language: &'a str,
+    search: &'a str,
+    sort: &'static str,
+    order: &'static str,
+    grouped: bool,
+    published_at_sort_url: String,
+    semver_sort_url: String,
     cache_expired_at: String,
 }
 
+#[derive(Template)]
+#[template(path = "feed_atom.xml")]
+struct AtomTemplate<'a> {
+    url: String,
+    updated_at: String,
+    entries: &'a [FeedEntry],
+}
+
+#[derive(Template)]
+#[template(path = "feed_rss.xml")]
+struct RssTemplate<'a> {
+    url: String,
+    site_url: String,
+    updated_at: String,
+    entries: &'a [FeedEntry],
+}
+
 // Route: GET "/github/{username}/{repository}"
 // curl -H "Content-Type: application/json" http://127.0.0.1:8089/github/actix/actix-web
 pub async fn github(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse, AppError> {
@@ -24,67 +55,249 @@ pub async fn github(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpR
     };
 
     let project = Project::new(repo.clone(), format!("{}/{}", user, repo), "Unknown".to_owned());
+    // Arbitrary repositories are not recorded to keep the `project` label bounded
     let release = project
-        .get_info(&data.github_api_username, &data.github_api_token)
+        .get_info(&data.github_api_username, &data.github_api_token, None)
         .await;
     Ok(HttpResponse::Ok().json(release))
 }
 
 // Route: GET "/github/async"
-// curl -H "Content-Type: application/json" http://127.0.0.1:8089/github/async
-pub async fn github_async(data: web::Data<AppState>) -> Result<HttpResponse, AppError> {
-    let cache = &mut data.releases.lock();
-    let mut _empty: Vec<Release> = Vec::new();
-    let (releases, _) = match cache {
-        Ok(c) => {
-            c.get_releases(data.github_api_username.clone(), data.github_api_token.clone())
-                .await
-        }
-        Err(e) => {
-            error!("{}", e);
-            let projects = Project::from_file(PROJECTS_FILE);
-            _empty = Release::get_all(
-                projects,
-                &data.github_api_username.clone(),
-                &data.github_api_token.clone(),
-            )
-            .await;
-            (&_empty, Utc::now())
-        }
-    };
-    Ok(HttpResponse::Ok().json(releases))
+// curl -H "Content-Type: application/json" "http://127.0.0.1:8089/github/async?language=Rust&sort=semver&order=asc"
+pub async fn github_async(
+    data: web::Data<AppState>,
+    pool: web::Data<MysqlPool>,
+    query: web::Query<ReleasesQuery>,
+) -> Result<HttpResponse, AppError> {
+    let (releases, _) = get_releases(&data, &pool).await;
+    match query.group_by {
+        Some(_) => Ok(HttpResponse::Ok().json(query.apply_grouped(&releases))),
+        None => Ok(HttpResponse::Ok().json(query.apply(&releases))),
+    }
 }
 
 // Route: GET "/github-page"
-pub async fn github_page(data: web::Data<AppState>) -> Result<HttpResponse, AppError> {
-    let cache = &mut data.releases.lock();
-    let mut _empty: Vec<Release> = Vec::new();
-    let (releases, cache_expired_at) = match cache {
-        Ok(c) => {
-            c.get_releases(data.github_api_username.clone(), data.github_api_token.clone())
-                .await
-        }
-        Err(e) => {
-            error!("{}", e);
-            let projects = Project::from_file(PROJECTS_FILE);
-            _empty = Release::get_all(
-                projects,
-                &data.github_api_username.clone(),
-                &data.github_api_token.clone(),
-            )
-            .await;
-            (&_empty, Utc::now())
-        }
-    };
+// http://127.0.0.1:8089/github-page?search=actix&sort=published_at&order=desc&group_by=language
+pub async fn github_page(
+    data: web::Data<AppState>,
+    pool: web::Data<MysqlPool>,
+    query: web::Query<ReleasesQuery>,
+) -> Result<HttpResponse, AppError> {
+    let (releases, cache_expired_at) = get_releases(&data, &pool).await;
+
+    let mut languages: Vec<String> = releases.iter().map(|r| r.language().to_owned()).collect();
+    languages.sort();
+    languages.dedup();
+
     GithubTemplate {
-        _releases: releases,
+        groups: &query.apply_grouped(&releases),
+        languages,
+        language: query.language().unwrap_or_default(),
+        search: query.search().unwrap_or_default(),
+        sort: query.sort().as_str(),
+        order: query.order().as_str(),
+        grouped: query.group_by.is_some(),
+        published_at_sort_url: sort_url(&query, ReleasesSort::PublishedAt),
+        semver_sort_url: sort_url(&query, ReleasesSort::Semver),
         cache_expired_at: cache_expired_at.to_rfc2822(),
     }
     .into_response()
-    .map_err(|e| {
-        error!("{}", e);
-        AppError::InternalError {
-            message: "Failed to load GithubTemplate.".to_owned(),
-        }
-    })
+    .map_err(|e| AppError::internal("Failed to load GithubTemplate.", e.to_string()))
+}
+
+// Route: GET "/github/feed.atom"
+// curl http://127.0.0.1:8089/github/feed.atom?language=Rust
+pub async fn github_feed_atom(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    pool: web::Data<MysqlPool>,
+    query: web::Query<FeedQuery>,
+) -> Result<HttpResponse, AppError> {
+    let (releases, cache_expired_at) = get_releases(&data, &pool).await;
+    let entries = get_feed_entries(&releases, &query.language);
+
+    let content = AtomTemplate {
+        url: request_url(&req),
+        updated_at: feed_updated_at(&entries).to_rfc3339(),
+        entries: &entries,
+    }
+    .render()
+    .map_err(|e| AppError::internal("Failed to load AtomTemplate.", e))?;
+
+    Ok(feed_response(cache_expired_at, feed_updated_at(&entries))
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(content))
+}
+
+// Route: GET "/github/feed.rss"
+// curl http://127.0.0.1:8089/github/feed.rss?language=Rust
+pub async fn github_feed_rss(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    pool: web::Data<MysqlPool>,
+    query: web::Query<FeedQuery>,
+) -> Result<HttpResponse, AppError> {
+    let (releases, cache_expired_at) = get_releases(&data, &pool).await;
+    let entries = get_feed_entries(&releases, &query.language);
+
+    let info = req.connection_info();
+    let content = RssTemplate {
+        url: request_url(&req),
+        site_url: format!("{}://{}/github-page", info.scheme(), info.host()),
+        updated_at: feed_updated_at(&entries).to_rfc2822(),
+        entries: &entries,
+    }
+    .render()
+    .map_err(|e| AppError::internal("Failed to load RssTemplate.", e))?;
+
+    Ok(feed_response(cache_expired_at, feed_updated_at(&entries))
+        .content_type("application/rss+xml; charset=utf-8")
+        .body(content))
+}
+
+/// Get releases and cache expiration date from the releases cache
+pub(crate) async fn get_releases(data: &AppState, pool: &MysqlPool) -> (Arc<Vec<Release>>, DateTime<Utc>) {
+    data.releases
+        .get_releases(&data.github_api_username, &data.github_api_token, pool.clone())
+        .await
+}
+
+/// Returns the Github page URL sorted by `sort`.
+/// If the page is already sorted by `sort`, the order is reversed.
+fn sort_url(query: &ReleasesQuery, sort: ReleasesSort) -> String {
+    let order = match (query.sort() == sort, query.order()) {
+        (true, SortOrder::Desc) => SortOrder::Asc,
+        _ => SortOrder::Desc,
+    };
+    let query = ReleasesQuery {
+        sort: Some(sort),
+        order: Some(order),
+        ..query.clone()
+    };
+    format!(
+        "/github-page?{}",
+        serde_urlencoded::to_string(&query).unwrap_or_default()
+    )
+}
+
+/// Get feed entries, most recent first, optionally filtered by project language
+fn get_feed_entries(releases: &[Release], language: &Option<String>) -> Vec<FeedEntry> {
+    let mut entries: Vec<FeedEntry> = releases
+        .iter()
+        .filter(|release| match language {
+            Some(language) => release.has_language(language),
+            None => true,
+        })
+        .filter_map(Release::to_feed_entry)
+        .collect();
+    entries.sort_by_key(|entry| Reverse(entry.published_at));
+    entries
+}
+
+/// Feed last update is the date of the most recent release
+fn feed_updated_at(entries: &[FeedEntry]) -> DateTime<Utc> {
+    entries.first().map_or_else(Utc::now, |entry| entry.published_at)
+}
+
+/// Returns the full URL of the request
+fn request_url(req: &HttpRequest) -> String {
+    let info = req.connection_info();
+    format!("{}://{}{}", info.scheme(), info.host(), req.uri())
+}
+
+/// Initialize a feed response with caching headers derived from the cache expiration date
+fn feed_response(cache_expired_at: DateTime<Utc>, updated_at: DateTime<Utc>) -> HttpResponseBuilder {
+    let max_age = (cache_expired_at - Utc::now()).num_seconds().max(0) as u32;
+
+    let mut response = HttpResponse::Ok();
+    response
+        .set(header::CacheControl(vec![
+            header::CacheDirective::Public,
+            header::CacheDirective::MaxAge(max_age),
+        ]))
+        .set(header::Expires(SystemTime::from(cache_expired_at).into()))
+        .set(header::LastModified(SystemTime::from(updated_at).into()));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::models::release::ReleasesCache;
+    use actix_web::http::{HeaderMap, StatusCode};
+    use actix_web::{test, App};
+    use chrono::Duration;
+
+    /// Calls a feed with releases already in the cache for 30 minutes
+    async fn get_feed(uri: &str) -> (StatusCode, HeaderMap, String) {
+        let releases = vec![
+            Release::fake("actix-web", "Rust", "v3.3.2", "2020-12-01T10:00:00Z"),
+            Release::fake("laravel", "PHP", "v8.0.0", "2020-12-02T10:00:00Z"),
+        ];
+        let mut data = AppState::new("secret".to_owned(), "".to_owned(), "".to_owned());
+        data.releases = Arc::new(ReleasesCache::loaded(releases, Utc::now() + Duration::minutes(30)));
+
+        let mut app = test::init_service(
+            App::new()
+                .data(db::lazy_pool())
+                .data(data)
+                .route("/github/feed.atom", web::get().to(github_feed_atom))
+                .route("/github/feed.rss", web::get().to(github_feed_rss)),
+        )
+        .await;
+        let resp = test::call_service(&mut app, test::TestRequest::get().uri(uri).to_request()).await;
+        let (status, headers) = (resp.status(), resp.headers().clone());
+        let body = test::read_body(resp).await;
+        (status, headers, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn header(headers: &HeaderMap, name: header::HeaderName) -> &str {
+        headers.get(name).unwrap().to_str().unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_feed_atom() {
+        let (status, headers, body) = get_feed("/github/feed.atom").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            header(&headers, header::CONTENT_TYPE),
+            "application/atom+xml; charset=utf-8"
+        );
+        assert!(body.contains("<updated>2020-12-02T10:00:00+00:00</updated>"));
+
+        // Most recent first
+        let laravel = body.find("<title>laravel v8.0.0</title>").unwrap();
+        let actix = body.find("<title>actix-web v3.3.2</title>").unwrap();
+        assert!(laravel < actix);
+        assert!(body.contains(r#"<category term="Rust" />"#));
+        assert!(body.contains("&lt;strong&gt;v3.3.2"));
+    }
+
+    #[actix_rt::test]
+    async fn test_feed_rss_language() {
+        let (status, headers, body) = get_feed("/github/feed.rss?language=rust").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            header(&headers, header::CONTENT_TYPE),
+            "application/rss+xml; charset=utf-8"
+        );
+        assert!(body.contains("actix-web v3.3.2"));
+        assert!(!body.contains("laravel"));
+        assert!(body.contains("Tue, 01 Dec 2020 10:00:00 +0000"));
+    }
+
+    #[actix_rt::test]
+    async fn test_feed_cache_headers() {
+        let (_, headers, _) = get_feed("/github/feed.atom?language=php").await;
+
+        let cache_control = header(&headers, header::CACHE_CONTROL);
+        let max_age: i64 = cache_control
+            .strip_prefix("public, max-age=")
+            .and_then(|max_age| max_age.parse().ok())
+            .unwrap();
+        assert!((1790..=1800).contains(&max_age), "{}", cache_control);
+        assert_eq!(header(&headers, header::LAST_MODIFIED), "Wed, 02 Dec 2020 10:00:00 GMT");
+        assert!(headers.contains_key(header::EXPIRES));
+    }
 }
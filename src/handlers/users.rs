@@ -5,10 +5,18 @@ use crate::db::MysqlPool;
 use crate::errors::AppError;
 use crate::models::auth::JWT;
 use crate::models::user::{Login, LoginResponse, NewUser, User, UserList};
+use crate::sse::{Broadcaster, Event, Publish};
+use crate::streaming::{StreamFormat, StreamResponse};
 use crate::AppState;
-use actix_web::{web, HttpRequest, HttpResponse};
+use actix::SystemService;
+use actix_web::{error::BlockingError, web, HttpRequest, HttpResponse};
 use chrono::prelude::*;
 use color_eyre::Result;
+use diesel::result::{DatabaseErrorKind, Error as DBError};
+use futures::stream::{self, TryStreamExt};
+
+/// Number of users read from the database at once during exports
+const EXPORT_CHUNK_SIZE: i64 = 1_000;
 
 // Route: POST "/login"
 // curl -H "Content-Type: application/json" -X POST http://127.0.0.1:8089/v1/login \
@@ -20,12 +28,15 @@ pub async fn login(
 ) -> Result<HttpResponse, AppError> {
     let mysql_pool = db::mysql_pool_handler(pool)?;
 
-    let user = web::block(move || User::login(&mysql_pool, form.into_inner()))
-        .await
-        .map_err(|e| {
-            error!("{}", e);
-            AppError::Unauthorized {}
-        })?;
+    let user = db::block(&data.metrics, "users.login", move || {
+        User::login(&mysql_pool, form.into_inner())
+    })
+    .await
+    .map_err(|e| {
+        error!("{}", e);
+        data.metrics.logins.with_label_values(&["failure"]).inc();
+        AppError::Unauthorized {}
+    })?;
 
     // Génération du token
     // -------------------
@@ -42,6 +53,7 @@ pub async fn login(
         Ok(token) => {
             let expires_at = chrono::NaiveDateTime::from_timestamp(token.1, 0);
             let expires_at: DateTime<Utc> = DateTime::from_utc(expires_at, Utc);
+            data.metrics.logins.with_label_values(&["success"]).inc();
 
             Ok(HttpResponse::Ok().json(LoginResponse {
                 lastname: user.lastname.to_owned(),
@@ -51,75 +63,132 @@ pub async fn login(
                 expires_at: expires_at.to_rfc3339_opts(SecondsFormat::Secs, true), // format("%Y-%m-%d %H:%M:%S").to_string(),
             }))
         }
-        _ => Err(AppError::Unauthorized {}),
+        _ => {
+            data.metrics.logins.with_label_values(&["failure"]).inc();
+            Err(AppError::Unauthorized {})
+        }
     }
 }
 
 // Route: POST "/register"
 // curl -H "Content-Type: application/json" -X POST http://127.0.0.1:8089/v1/register \
 // -d '{"lastname":"Bellanger", "firstname":"Fabien", "email":"fabien.bellanger3@test.com", "password": "0000"}'
-pub async fn create(pool: web::Data<MysqlPool>, form: web::Json<NewUser>) -> Result<HttpResponse, AppError> {
+pub async fn create(
+    pool: web::Data<MysqlPool>,
+    data: web::Data<AppState>,
+    form: web::Json<NewUser>,
+) -> Result<HttpResponse, AppError> {
     let mysql_pool = db::mysql_pool_handler(pool)?;
 
-    let user = web::block(move || User::create(&mysql_pool, form.into_inner()))
-        .await
-        .map_err(|e| {
-            error!("{}", e);
-            AppError::InternalError {
-                message: "Error during user creation".to_owned(),
-            }
-        })?;
+    let user = db::block(&data.metrics, "users.create", move || {
+        User::create(&mysql_pool, form.into_inner())
+    })
+    .await
+    .map_err(|e| match e {
+        BlockingError::Error(e @ DBError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => e.into(),
+        e => AppError::internal("Error during user creation", e),
+    })?;
+
+    Broadcaster::from_registry().do_send(Publish {
+        event: Event::UserCreated { id: user.id.clone() },
+    });
 
     Ok(HttpResponse::Ok().json(user))
 }
 
 // Route: GET "/users"
 // curl http://localhost:8089/v1/users -H 'Authorization: Bearer '
-pub async fn get_users(pool: web::Data<MysqlPool>, _req: HttpRequest) -> Result<HttpResponse, AppError> {
+pub async fn get_users(
+    pool: web::Data<MysqlPool>,
+    data: web::Data<AppState>,
+    _req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
     let mysql_pool = db::mysql_pool_handler(pool)?;
 
-    let users = web::block(move || UserList::list(&mysql_pool)).await.map_err(|e| {
-        error!("{}", e);
-        AppError::InternalError {
-            message: "Error while retrieving users list".to_owned(),
-        }
-    })?;
+    let users = db::block(&data.metrics, "users.list", move || UserList::list(&mysql_pool))
+        .await
+        .map_err(|e| AppError::internal("Error while retrieving users list", e))?;
     Ok(HttpResponse::Ok().json(users))
 }
 
+// Route: GET "/users/export"
+// NDJSON by default or CSV with `Accept: text/csv`
+// curl http://localhost:8089/v1/users/export -H 'Authorization: Bearer ' -H 'Accept: text/csv'
+pub async fn export(pool: web::Data<MysqlPool>, data: web::Data<AppState>) -> StreamResponse<User> {
+    let pool = pool.get_ref().clone();
+    let metrics = data.metrics.clone();
+
+    // State: pool, metrics, ID of the last exported user and end of table flag
+    let users = stream::try_unfold(
+        (pool, metrics, None::<String>, false),
+        |(pool, metrics, after, done)| async move {
+            if done {
+                return Ok(None);
+            }
+
+            let connection_pool = pool.clone();
+            let UserList(users) = db::block(&metrics, "users.export", move || {
+                let connection = connection_pool.get().map_err(|e| e.to_string())?;
+                UserList::list_after(&connection, after.as_deref(), EXPORT_CHUNK_SIZE).map_err(|e| e.to_string())
+            })
+            .await
+            .map_err(|e| AppError::internal("Error while exporting users", e))?;
+
+            let last = users.last().map(|user| user.id.clone());
+            let done = (users.len() as i64) < EXPORT_CHUNK_SIZE;
+            let users = stream::iter(users.into_iter().map(Ok::<_, AppError>));
+            Ok::<_, AppError>(Some((users, (pool, metrics, last, done))))
+        },
+    )
+    .try_flatten();
+
+    StreamResponse::try_new(users).default_format(StreamFormat::NdJson)
+}
+
 // Route: GET "/users/{id}
 // curl http://localhost:8089/v1/users/<uuid>
-pub async fn get_by_id(pool: web::Data<MysqlPool>, web::Path(id): web::Path<String>) -> Result<HttpResponse, AppError> {
+pub async fn get_by_id(
+    pool: web::Data<MysqlPool>,
+    data: web::Data<AppState>,
+    web::Path(id): web::Path<String>,
+) -> Result<HttpResponse, AppError> {
     let mysql_pool = db::mysql_pool_handler(pool)?;
 
-    let user = web::block(move || User::get_by_id(&mysql_pool, id))
+    let user = db::block(&data.metrics, "users.get", move || User::get_by_id(&mysql_pool, id))
         .await
-        .map_err(|e| {
-            error!("{}", e);
-            AppError::InternalError {
-                message: "Error while retrieving a user's information".to_owned(),
-            }
+        .map_err(|e| match e {
+            BlockingError::Error(DBError::NotFound) => AppError::NotFound {
+                message: "User not found".to_owned(),
+            },
+            e => AppError::internal("Error while retrieving a user's information", e),
         })?;
     Ok(HttpResponse::Ok().json(user))
 }
 
 // Route: DELETE "/users/{id}"
 // curl -X DELETE http://127.0.0.1:8089/v1/users/<uuid>
-pub async fn delete(web::Path(id): web::Path<String>, pool: web::Data<MysqlPool>) -> Result<HttpResponse, AppError> {
+pub async fn delete(
+    web::Path(id): web::Path<String>,
+    pool: web::Data<MysqlPool>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, AppError> {
     let mysql_pool = db::mysql_pool_handler(pool)?;
 
-    let num_deleted = web::block(move || User::delete(&mysql_pool, id)).await.map_err(|e| {
-        error!("{}", e);
-        AppError::InternalError {
-            message: "Error during user deletion".to_owned(),
-        }
-    })?;
+    let user_id = id.clone();
+    let num_deleted = db::block(&data.metrics, "users.delete", move || User::delete(&mysql_pool, id))
+        .await
+        .map_err(|e| AppError::internal("Error during user deletion", e))?;
 
     match num_deleted {
         0 => Err(AppError::NotFound {
             message: "User not found".to_owned(),
         }),
-        _ => Ok(HttpResponse::Ok().finish()),
+        _ => {
+            Broadcaster::from_registry().do_send(Publish {
+                event: Event::UserDeleted { id: user_id },
+            });
+            Ok(HttpResponse::Ok().finish())
+        }
     }
 }
 
@@ -127,21 +196,22 @@ pub async fn delete(web::Path(id): web::Path<String>, pool: web::Data<MysqlPool>
 // curl -H "Content-Type: application/json" -X PUT http://127.0.0.1:8089/v1/users/<uuid> -d '{"lastname":"Bellanger", "firstname":"Fabien"}'
 pub async fn update(
     pool: web::Data<MysqlPool>,
+    data: web::Data<AppState>,
     web::Path(id): web::Path<String>,
     form: web::Json<NewUser>,
 ) -> Result<HttpResponse, AppError> {
     let mysql_pool = db::mysql_pool_handler(pool)?;
 
-    let user = web::block(move || User::update(&mysql_pool, id, form.into_inner()))
-        .await
-        .map_err(|e| match e.to_string().as_str() {
-            "NotFound" => AppError::NotFound {
-                message: "User not found".to_owned(),
-            },
-            _ => AppError::InternalError {
-                message: "Error during user update".to_owned(),
-            },
-        })?;
+    let user = db::block(&data.metrics, "users.update", move || {
+        User::update(&mysql_pool, id, form.into_inner())
+    })
+    .await
+    .map_err(|e| match e {
+        BlockingError::Error(DBError::NotFound) => AppError::NotFound {
+            message: "User not found".to_owned(),
+        },
+        e => AppError::internal("Error during user update", e),
+    })?;
 
     Ok(HttpResponse::Ok().json(user))
 }
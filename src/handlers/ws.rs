@@ -1,15 +1,190 @@
 //! WebSockets handlers.
 
-use crate::ws::WebSocket;
-use actix_web::{web, Error, HttpRequest, HttpResponse};
+use crate::errors::AppError;
+use crate::models::auth::{Claims, TokenQuery, JWT};
+use crate::ws::codec::Encoding;
+use crate::ws::{WebSocket, WsSettings};
+use crate::AppState;
+use actix_http::ws::Codec;
+use actix_web::{http::header, web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
 use color_eyre::Result;
 
+/// Subprotocol used by browsers to send the token in `Sec-WebSocket-Protocol` header:
+/// `new WebSocket(url, ["access_token", token])`
+const TOKEN_PROTOCOL: &str = "access_token";
+
 /// Connect the client
 // Route: GET "/ws"
-// ws://127.0.0.1:8089/ws
-pub async fn index(req: HttpRequest, stream: web::Payload) -> Result<HttpResponse, Error> {
-    let resp = ws::start(WebSocket {}, &req, stream);
+// ws://127.0.0.1:8089/ws?token=<token> (subprotocols: json.v1, msgpack.v1 or cbor.v1)
+pub async fn index(
+    req: HttpRequest,
+    stream: web::Payload,
+    data: web::Data<AppState>,
+    settings: Option<web::Data<WsSettings>>,
+) -> Result<HttpResponse, Error> {
+    let claims = authenticate(&req, &data.jwt_secret_key)?;
+
+    let encoding = negotiate(&req);
+
+    let settings = settings.map(|s| s.get_ref().clone()).unwrap_or_default();
+    let max_frame_size = settings.max_frame_size;
+
+    let session = WebSocket::new(claims, encoding, settings, data.metrics.clone());
+    let protocol = encoding.protocol().unwrap_or(TOKEN_PROTOCOL);
+    let resp = start(session, &[protocol], max_frame_size, &req, stream);
     debug!("WS Client Response: {:?}", resp);
     resp
 }
+
+/// Connect a read-only client receiving releases changes
+// Route: GET "/ws/releases"
+// ws://127.0.0.1:8089/ws/releases
+pub async fn releases(
+    req: HttpRequest,
+    stream: web::Payload,
+    data: web::Data<AppState>,
+    settings: Option<web::Data<WsSettings>>,
+) -> Result<HttpResponse, Error> {
+    let encoding = negotiate(&req);
+
+    let settings = settings.map(|s| s.get_ref().clone()).unwrap_or_default();
+    let max_frame_size = settings.max_frame_size;
+
+    let session = WebSocket::releases(encoding, settings, data.metrics.clone());
+    let protocols: Vec<&str> = encoding.protocol().into_iter().collect();
+    start(session, &protocols, max_frame_size, &req, stream)
+}
+
+/// Do the handshake and start the session with a limited frame size
+fn start(
+    session: WebSocket,
+    protocols: &[&str],
+    max_frame_size: usize,
+    req: &HttpRequest,
+    stream: web::Payload,
+) -> Result<HttpResponse, Error> {
+    let mut resp = ws::handshake_with_protocols(req, protocols)?;
+    let codec = Codec::new().max_size(max_frame_size);
+    Ok(resp.streaming(ws::WebsocketContext::with_codec(session, stream, codec)))
+}
+
+/// Chooses the messages encoding from the `Sec-WebSocket-Protocol` header.
+/// The chosen subprotocol is sent back instead of the token one if both are requested.
+fn negotiate(req: &HttpRequest) -> Encoding {
+    req.headers()
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|protocols| protocols.to_str().ok())
+        .map(Encoding::negotiate)
+        .unwrap_or_default()
+}
+
+/// Parses the token from, in order, `Authorization` header, `Sec-WebSocket-Protocol` header
+/// or `token` query parameter
+pub(crate) fn authenticate(req: &HttpRequest, secret_key: &str) -> Result<Claims, AppError> {
+    let token = bearer_token(req)
+        .or_else(|| protocol_token(req))
+        .or_else(|| query_token(req))
+        .ok_or(AppError::Unauthorized)?;
+
+    JWT::parse(token, secret_key.to_owned()).map_err(|e| {
+        error!("WS: failed to parse token: {}", e);
+        AppError::Unauthorized
+    })
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<String> {
+    let auth = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    if auth.starts_with("bearer") || auth.starts_with("Bearer") {
+        Some(auth[6..].trim().to_owned())
+    } else {
+        None
+    }
+}
+
+fn protocol_token(req: &HttpRequest) -> Option<String> {
+    let protocols = req.headers().get(header::SEC_WEBSOCKET_PROTOCOL)?.to_str().ok()?;
+    let mut protocols = protocols.split(',').map(str::trim);
+
+    protocols.find(|p| *p == TOKEN_PROTOCOL)?;
+    protocols.next().map(String::from)
+}
+
+fn query_token(req: &HttpRequest) -> Option<String> {
+    web::Query::<TokenQuery>::from_query(req.query_string())
+        .ok()
+        .map(|query| query.into_inner().token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ws::broker::{Broker, PublishReleases};
+    use crate::ws::protocol::ReleaseEvent;
+    use actix::SystemService;
+    use actix_http::ws::Frame;
+    use actix_web::{test, App};
+    use futures::StreamExt;
+    use serde_json::{json, Value};
+
+    fn event() -> ReleaseEvent {
+        ReleaseEvent {
+            name: "actix-web".to_owned(),
+            repo: "actix/actix-web".to_owned(),
+            language: "Rust".to_owned(),
+            tag_name: "v3.3.2".to_owned(),
+            previous_tag_name: "v3.3.1".to_owned(),
+            url: "https://github.com/actix/actix-web/releases/tag/v3.3.2".to_owned(),
+            published_at: "2020-12-01T10:00:00Z".to_owned(),
+        }
+    }
+
+    /// Reads the next text frame as JSON
+    async fn next_json<S>(framed: &mut S) -> Value
+    where
+        S: futures::Stream<Item = Result<Frame, actix_http::ws::ProtocolError>> + Unpin,
+    {
+        match framed.next().await {
+            Some(Ok(Frame::Text(text))) => serde_json::from_slice(&text).expect("invalid JSON message"),
+            frame => panic!("unexpected frame: {:?}", frame),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_releases_push() {
+        // Releases are published from the server system, whose broker is not the test one
+        let mut srv = test::start(|| {
+            App::new()
+                .data(AppState::new("secret".to_owned(), "".to_owned(), "".to_owned()))
+                .route("/ws/releases", web::get().to(releases))
+                .route(
+                    "/publish",
+                    web::post().to(|| {
+                        Broker::from_registry().do_send(PublishReleases { events: vec![event()] });
+                        HttpResponse::Ok().finish()
+                    }),
+                )
+        });
+
+        let mut client = srv.ws_at("/ws/releases").await.unwrap();
+        assert_eq!(next_json(&mut client).await["type"], "welcome");
+        assert_eq!(next_json(&mut client).await["room"], "releases");
+
+        assert!(srv.post("/publish").send().await.unwrap().status().is_success());
+        assert_eq!(
+            next_json(&mut client).await,
+            json!({
+                "type": "release_changed",
+                "release": {
+                    "name": "actix-web",
+                    "repo": "actix/actix-web",
+                    "language": "Rust",
+                    "tag_name": "v3.3.2",
+                    "previous_tag_name": "v3.3.1",
+                    "url": "https://github.com/actix/actix-web/releases/tag/v3.3.2",
+                    "published_at": "2020-12-01T10:00:00Z",
+                },
+            })
+        );
+    }
+}
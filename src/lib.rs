@@ -3,9 +3,14 @@ mod db;
 mod errors;
 pub mod handlers;
 mod logger;
-mod middlewares;
+mod metrics;
+pub mod middlewares;
 mod models;
+mod negotiation;
 mod routes;
+mod sse;
+mod streaming;
+mod telemetry;
 mod ws;
 
 #[macro_use]
@@ -19,21 +24,49 @@ extern crate chrono;
 extern crate serde;
 
 use crate::config::Config;
+use crate::metrics::Metrics;
+use crate::models::health::HealthSettings;
 use crate::models::release::ReleasesCache;
+use crate::ws::WsSettings;
 use actix_cors::Cors;
-use actix_web::middleware::errhandlers::ErrorHandlers;
-use actix_web::middleware::Logger;
-use actix_web::{http, App, HttpServer};
+use actix_web::{http, web, App, HttpServer};
 use actix_web_prom::PrometheusMetrics;
 use color_eyre::Result;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Interval between two releases cache expiration checks (in seconds)
+const RELEASES_REFRESH_INTERVAL: u64 = 60;
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub jwt_secret_key: String,
     pub github_api_username: String,
     pub github_api_token: String,
-    pub releases: Arc<Mutex<ReleasesCache>>,
+    pub releases: Arc<ReleasesCache>,
+    pub metrics: Metrics,
+}
+
+impl AppState {
+    /// Creates the application state with an empty releases cache
+    pub fn new(jwt_secret_key: String, github_api_username: String, github_api_token: String) -> Self {
+        Self {
+            jwt_secret_key,
+            github_api_username,
+            github_api_token,
+            releases: Arc::new(ReleasesCache::default()),
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Records application metrics in `metrics` instead of unregistered ones
+    pub fn with_metrics(self, metrics: Metrics) -> Self {
+        Self {
+            releases: Arc::new(ReleasesCache::new(metrics.clone())),
+            metrics,
+            ..self
+        }
+    }
 }
 
 pub async fn run() -> Result<()> {
@@ -44,6 +77,7 @@ pub async fn run() -> Result<()> {
     let jwt_secret_key = settings.jwt_secret_key;
     let github_api_username = settings.github_api_username;
     let github_api_token = settings.github_api_token;
+    let compression_min_size = settings.compression_min_size;
 
     // Installation de Color Eyre
     // --------------------------
@@ -51,42 +85,135 @@ pub async fn run() -> Result<()> {
 
     // Logger
     // ------
-    logger::init(settings.server_log_level);
+    logger::init(&settings.environment, &settings.server_log_level);
+
+    // Tracing
+    // -------
+    let _telemetry = telemetry::init(
+        settings
+            .tracing_exporter
+            .parse()
+            .expect("Invalid TRACING_EXPORTER value"),
+        &settings.tracing_otlp_endpoint,
+        &settings.tracing_service_name,
+    )?;
+
+    // Prometheus
+    // ----------
+    // `/metrics` is served by `handlers::metrics` with the operational routes
+    let prometheus = PrometheusMetrics::new("api", None, None);
+    let registry = prometheus.registry.clone();
+
+    // Custom metrics
+    // --------------
+    let metrics = Metrics::new("api", &prometheus.registry).expect("Failed to register metrics.");
 
     // Initialisation du state de l'application
     // ----------------------------------------
-    let data = AppState {
-        jwt_secret_key: jwt_secret_key.clone(),
-        github_api_username: github_api_username.clone(),
-        github_api_token: github_api_token.clone(),
-        releases: Arc::new(Mutex::new(ReleasesCache::new())),
-    };
+    let data = AppState::new(jwt_secret_key, github_api_username, github_api_token).with_metrics(metrics.clone());
 
     // Initialisation du pool MySQL via r2d2
     // -------------------------------------
-    let pool = db::init(&db_url).expect("Failed to create MySQL pool.");
+    let pool = db::init(&db_url, &metrics).expect("Failed to create MySQL pool.");
+    prometheus
+        .registry
+        .register(Box::new(
+            metrics::PoolCollector::new("api", pool.clone()).expect("Failed to create pool metrics."),
+        ))
+        .expect("Failed to register pool metrics.");
 
-    // Prometheus
+    // Access log
     // ----------
-    let prometheus = PrometheusMetrics::new("api", Some("/metrics"), None);
+    let slow_threshold = Duration::from_millis(settings.access_log_slow_threshold);
+    let slow_routes = middlewares::access_log::parse_route_thresholds(&settings.access_log_slow_routes)
+        .expect("Invalid ACCESS_LOG_SLOW_ROUTES value");
+
+    // Operational endpoints
+    // ---------------------
+    let admin_guard = middlewares::admin::parse_networks(&settings.admin_allowed_ips)
+        .expect("Invalid ADMIN_ALLOWED_IPS value")
+        .into_iter()
+        .fold(middlewares::admin::AdminGuard::new(), |guard, network| {
+            guard.allow(network)
+        });
+    let admin_guard = match settings.admin_basic_auth.as_str() {
+        "" => admin_guard,
+        credentials => admin_guard.basic_auth(credentials),
+    };
+    let admin_bind = match settings.admin_server_port.as_str() {
+        "" => None,
+        port => Some(format!("{}:{}", settings.admin_server_url, port)),
+    };
+
+    // Health checks
+    // -------------
+    let health_settings = HealthSettings {
+        timeout: Duration::from_millis(settings.health_check_timeout),
+    };
+
+    // WebSockets
+    // ----------
+    let ws_settings = WsSettings {
+        heartbeat_interval: Duration::from_secs(settings.ws_heartbeat_interval),
+        client_timeout: Duration::from_secs(settings.ws_client_timeout),
+        max_frame_size: settings.ws_max_frame_size,
+        max_message_size: settings.ws_max_message_size,
+        outbound_queue_size: settings.ws_outbound_queue_size,
+        outbound_queue_policy: settings
+            .ws_outbound_queue_policy
+            .parse()
+            .expect("Invalid WS_OUTBOUND_QUEUE_POLICY value"),
+        rate_limit: settings.ws_rate_limit,
+    };
+
+    // Releases cache refresh
+    // ----------------------
+    // Keeps the cache up to date so that new releases are pushed to WebSocket clients
+    let state = data.clone();
+    let refresh_pool = pool.clone();
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(Duration::from_secs(RELEASES_REFRESH_INTERVAL));
+        loop {
+            interval.tick().await;
+            state
+                .releases
+                .get_releases(
+                    &state.github_api_username,
+                    &state.github_api_token,
+                    refresh_pool.clone(),
+                )
+                .await;
+        }
+    });
+
+    // Start servers
+    // -------------
+    let ops_on_public = admin_bind.is_none();
+    let public_guard = admin_guard.clone();
+    let public_registry = registry.clone();
+    let public_health_settings = health_settings.clone();
+    let (admin_pool, admin_data) = (pool.clone(), data.clone());
+    let server = HttpServer::new(move || {
+        let access_log = slow_routes.iter().fold(
+            middlewares::access_log::AccessLog::new(metrics.clone()).slow_threshold(slow_threshold),
+            |access_log, (route, threshold)| access_log.route_threshold(route, *threshold),
+        );
 
-    // Start server
-    // ------------
-    HttpServer::new(move || {
         App::new()
             .data(pool.clone())
             .data(data.clone())
+            .data(ws_settings.clone())
+            .data(public_registry.clone())
+            .data(public_health_settings.clone())
+            .wrap(middlewares::errors::ErrorRenderer)
             .wrap(
-                ErrorHandlers::new()
-                    .handler(http::StatusCode::UNAUTHORIZED, handlers::errors::render_401)
-                    .handler(http::StatusCode::FORBIDDEN, handlers::errors::render_403)
-                    .handler(http::StatusCode::REQUEST_TIMEOUT, handlers::errors::render_408)
-                    .handler(http::StatusCode::BAD_GATEWAY, handlers::errors::render_502)
-                    .handler(http::StatusCode::SERVICE_UNAVAILABLE, handlers::errors::render_503)
-                    .handler(http::StatusCode::GATEWAY_TIMEOUT, handlers::errors::render_504),
+                middlewares::compress::Compress::new()
+                    .min_size(compression_min_size)
+                    .exclude("/ws")
+                    .exclude("/metrics"),
             )
             .wrap(prometheus.clone())
-            .wrap(Logger::new("%s | %r | %Ts | %{User-Agent}i | %a"))
+            .wrap(access_log)
             .wrap(
                 Cors::new()
                     // .allowed_origin("*")
@@ -97,14 +224,40 @@ pub async fn run() -> Result<()> {
                     .max_age(3600)
                     .finish(),
             )
-            .wrap(middlewares::timer::Timer)
+            .wrap(middlewares::telemetry::Telemetry)
             .wrap(middlewares::request_id::RequestId)
             .configure(routes::api)
             .configure(routes::web)
+            .configure(|cfg| {
+                if ops_on_public {
+                    routes::ops(cfg, public_guard.clone())
+                }
+            })
+            .default_service(web::route().to(handlers::errors::not_found))
     })
     .bind(format!("{}:{}", settings.server_url, settings.server_port))?
-    .run()
-    .await?;
+    .run();
+
+    match admin_bind {
+        Some(admin_bind) => {
+            let admin_server = HttpServer::new(move || {
+                App::new()
+                    .data(admin_pool.clone())
+                    .data(admin_data.clone())
+                    .data(registry.clone())
+                    .data(health_settings.clone())
+                    .wrap(middlewares::errors::ErrorRenderer)
+                    .wrap(middlewares::request_id::RequestId)
+                    .configure(|cfg| routes::ops(cfg, admin_guard.clone()))
+                    .default_service(web::route().to(handlers::errors::not_found))
+            })
+            .workers(1)
+            .bind(admin_bind)?
+            .run();
+            futures::future::try_join(server, admin_server).await?;
+        }
+        None => server.await?,
+    }
 
     Ok(())
 }
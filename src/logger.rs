@@ -1,26 +1,178 @@
 //! Logger module for customize logs
 
+use crate::middlewares::request_id;
 use chrono::Local;
 use env_logger::fmt::Color;
 use env_logger::Builder;
-use log::Level;
+use log::{kv, Level, LevelFilter, Log, Metadata, Record};
+use serde_json::{json, Value};
+use std::fmt;
 use std::io::Write;
-use tracing::Level as TLevel;
-use tracing_subscriber::{fmt::format, prelude::*};
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// Log level filters, changed at runtime by `set_filters`
+static FILTERS: RwLock<Filters> = RwLock::new(Filters {
+    default: LevelFilter::Error,
+    modules: Vec::new(),
+});
+
+/// Log level filters: a default level and levels per module
+///
+/// Parsed from comma-separated directives like `info,actix_web=warn,test_actix=debug`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filters {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Filters {
+    /// Returns the level of the most specific module matching a target
+    fn level(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(module, _)| {
+                target == module || (target.starts_with(module.as_str()) && target[module.len()..].starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    /// Most verbose level of the filters
+    fn max(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, std::cmp::max)
+    }
+}
+
+impl FromStr for Filters {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filters = Self {
+            default: LevelFilter::Error,
+            modules: Vec::new(),
+        };
+
+        for directive in s.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            let parse =
+                |level: &str| LevelFilter::from_str(level.trim()).map_err(|_| format!("invalid log level: {}", level));
+            match directive.split_once('=') {
+                Some((module, level)) => filters.modules.push((module.trim().to_owned(), parse(level)?)),
+                None => filters.default = parse(directive)?,
+            }
+        }
+        Ok(filters)
+    }
+}
+
+impl fmt::Display for Filters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.default.to_string().to_lowercase())?;
+        for (module, level) in &self.modules {
+            write!(f, ",{}={}", module, level.to_string().to_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the current log level filters
+pub fn filters() -> Filters {
+    FILTERS
+        .read()
+        .map(|filters| filters.clone())
+        .unwrap_or_else(|e| e.into_inner().clone())
+}
+
+/// Replaces the log level filters
+pub fn set_filters(filters: Filters) {
+    log::set_max_level(filters.max());
+    match FILTERS.write() {
+        Ok(mut current) => *current = filters,
+        Err(e) => *e.into_inner() = filters,
+    }
+}
+
+/// Logger applying the current filters before writing records with `env_logger`
+struct FilteredLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for FilteredLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= filters_level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+fn filters_level(target: &str) -> LevelFilter {
+    match FILTERS.read() {
+        Ok(filters) => filters.level(target),
+        Err(e) => e.into_inner().level(target),
+    }
+}
+
+/// Collects the structured fields of a record
+fn key_values(record: &Record) -> Vec<(String, Value)> {
+    struct Collector(Vec<(String, Value)>);
+
+    impl<'kvs> kv::VisitSource<'kvs> for Collector {
+        fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+            let value = if let Some(value) = value.to_u64() {
+                Value::from(value)
+            } else if let Some(value) = value.to_i64() {
+                Value::from(value)
+            } else if let Some(value) = value.to_f64() {
+                Value::from(value)
+            } else {
+                Value::from(value.to_string())
+            };
+            self.0.push((key.to_string(), value));
+            Ok(())
+        }
+    }
+
+    let mut collector = Collector(Vec::new());
+    let _ = record.key_values().visit(&mut collector);
+    collector.0
+}
 
 /// Initialize logger
-pub fn init(level: String) {
-    let level = match &*level {
-        "trace" => Level::Trace,
-        "debug" => Level::Debug,
-        "info" => Level::Info,
-        "warn" => Level::Warn,
-        "Error" => Level::Error,
-        &_ => Level::Error,
-    };
-
-    Builder::new()
-        .format(move |buf, record| {
+///
+/// Logs are written as JSON lines in production and as colored text otherwise.
+pub fn init(environment: &str, filters: &str) {
+    let filters = filters.parse().expect("Invalid SERVER_LOG_LEVEL value");
+
+    let mut builder = Builder::new();
+    if environment == "production" {
+        builder.format(|buf, record| {
+            let mut line = json!({
+                "timestamp": Local::now().to_rfc3339(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "line": record.line(),
+                "request_id": request_id::current(),
+                "message": record.args().to_string(),
+            });
+            for (key, value) in key_values(record) {
+                line[key] = value;
+            }
+            writeln!(buf, "{}", line)
+        });
+    } else {
+        builder.format(|buf, record| {
             let mut level_style = buf.style();
 
             let (color, level_spaces) = match record.level() {
@@ -36,62 +188,38 @@ pub fn init(level: String) {
                 Some(line) => format!(":{}", line),
                 None => "".to_owned(),
             };
+            let request_id = match request_id::current() {
+                Some(request_id) => format!(" | {}", request_id),
+                None => "".to_owned(),
+            };
+
+            let fields: String = key_values(record)
+                .into_iter()
+                .map(|(key, value)| match value {
+                    Value::String(value) => format!(" {}={:?}", key, value),
+                    value => format!(" {}={}", key, value),
+                })
+                .collect();
 
             writeln!(
                 buf,
-                "{} [{}]{}{}{} | {}",
+                "{} [{}]{}{}{}{} | {}{}",
                 Local::now().format("%Y-%m-%dT%H:%M:%S"),
                 level_style.value(record.level()),
                 level_spaces,
                 record.target(),
                 line,
-                record.args()
+                request_id,
+                record.args(),
+                fields
             )
-        })
-        .filter(None, level.to_level_filter())
-        .init();
+        });
+    }
 
-    info!("Logger configuration OK");
-}
-
-pub fn _init_with_tracing(level: String) {
-    // Tracing
-    // -------
-    // tracing_subscriber::fmt()
-    //     // .with_env_filter(EnvFilter::from_default_env())
-    //     .with_max_level(tracing::Level::TRACE)
-    //     .init();
-    // https://github.com/tokio-rs/tracing/blob/master/examples/examples/fmt-multiple-writers.rs
-    // https://github.com/tokio-rs/tracing/blob/master/examples/examples/fmt-custom-field.rs
-
-    let level = match &*level {
-        "trace" => TLevel::TRACE,
-        "debug" => TLevel::DEBUG,
-        "info" => TLevel::INFO,
-        "warn" => TLevel::WARN,
-        "Error" => TLevel::ERROR,
-        &_ => TLevel::ERROR,
-    };
-
-    let format = format::debug_fn(|writer, field, value| {
-        // We'll format the field name and value separated with a colon.
-        if field.to_string() == "message" || field.to_string() == "log.line" {
-            write!(writer, " ({}: {:?}) ", field, value)
-        } else {
-            write!(writer, "")
-        }
-    })
-    .display_messages()
-    .delimited("");
-
-    tracing_subscriber::fmt()
-        .with_thread_names(false)
-        .with_max_level(level)
-        .fmt_fields(format)
-        .with_timer(tracing_subscriber::fmt::time::ChronoLocal::with_format(String::from(
-            "%Y-%m-%dT%H:%M:%S",
-        )))
-        .init();
+    // Records are filtered by `FilteredLogger`
+    let inner = builder.filter(None, LevelFilter::Trace).build();
+    log::set_boxed_logger(Box::new(FilteredLogger { inner })).expect("Logger already initialized");
+    set_filters(filters);
 
     info!("Logger configuration OK");
 }
@@ -0,0 +1,219 @@
+//! Application metrics module
+//!
+//! Custom metrics are registered in the `actix-web-prom` registry and exported on `/metrics`.
+
+use crate::db::MysqlPool;
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::{Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry};
+
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    /// Number of active WebSocket sessions
+    pub ws_active_sessions: IntGauge,
+    /// Number of WebSocket sessions closed after an idle timeout
+    pub ws_timeouts: IntCounter,
+    /// Requests duration by method, route pattern and status
+    pub http_route_duration: HistogramVec,
+    /// Time spent waiting for a database connection
+    pub db_pool_wait: Histogram,
+    /// Number of database connection checkouts which have timed out
+    pub db_pool_timeouts: IntCounter,
+    /// Time spent by database calls waiting for a blocking thread
+    pub db_block_queue: Histogram,
+    /// GitHub API calls duration by project
+    pub github_fetch_duration: HistogramVec,
+    /// Number of failed GitHub API calls by project
+    pub github_fetch_failures: IntCounterVec,
+    /// Number of releases served from the cache
+    pub releases_cache_hits: IntCounter,
+    /// Number of releases cache refreshes
+    pub releases_cache_misses: IntCounter,
+    /// Number of logins by result (`success` or `failure`)
+    pub logins: IntCounterVec,
+}
+
+impl Metrics {
+    /// Creates metrics and registers them in the Prometheus registry
+    pub fn new(namespace: &str, registry: &Registry) -> Result<Self, prometheus::Error> {
+        let metrics = Self::build(namespace)?;
+
+        registry.register(Box::new(metrics.ws_active_sessions.clone()))?;
+        registry.register(Box::new(metrics.ws_timeouts.clone()))?;
+        registry.register(Box::new(metrics.http_route_duration.clone()))?;
+        registry.register(Box::new(metrics.db_pool_wait.clone()))?;
+        registry.register(Box::new(metrics.db_pool_timeouts.clone()))?;
+        registry.register(Box::new(metrics.db_block_queue.clone()))?;
+        registry.register(Box::new(metrics.github_fetch_duration.clone()))?;
+        registry.register(Box::new(metrics.github_fetch_failures.clone()))?;
+        registry.register(Box::new(metrics.releases_cache_hits.clone()))?;
+        registry.register(Box::new(metrics.releases_cache_misses.clone()))?;
+        registry.register(Box::new(metrics.logins.clone()))?;
+
+        Ok(metrics)
+    }
+
+    fn build(namespace: &str) -> Result<Self, prometheus::Error> {
+        Ok(Self {
+            ws_active_sessions: IntGauge::with_opts(
+                Opts::new("ws_active_sessions", "Number of active WebSocket sessions").namespace(namespace),
+            )?,
+            ws_timeouts: IntCounter::with_opts(
+                Opts::new(
+                    "ws_timeouts_total",
+                    "Number of WebSocket sessions closed after an idle timeout",
+                )
+                .namespace(namespace),
+            )?,
+            http_route_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_route_duration_seconds",
+                    "Requests duration by method, route pattern and status (until the response body is sent)",
+                )
+                .namespace(namespace),
+                &["method", "route", "status"],
+            )?,
+            db_pool_wait: Histogram::with_opts(
+                HistogramOpts::new("db_pool_wait_seconds", "Time spent waiting for a database connection")
+                    .namespace(namespace),
+            )?,
+            db_pool_timeouts: IntCounter::with_opts(
+                Opts::new(
+                    "db_pool_timeouts_total",
+                    "Number of database connection checkouts which have timed out",
+                )
+                .namespace(namespace),
+            )?,
+            db_block_queue: Histogram::with_opts(
+                HistogramOpts::new(
+                    "db_block_queue_seconds",
+                    "Time spent by database calls waiting for a blocking thread",
+                )
+                .namespace(namespace),
+            )?,
+            github_fetch_duration: HistogramVec::new(
+                HistogramOpts::new("github_fetch_duration_seconds", "GitHub API calls duration by project")
+                    .namespace(namespace),
+                &["project"],
+            )?,
+            github_fetch_failures: IntCounterVec::new(
+                Opts::new(
+                    "github_fetch_failures_total",
+                    "Number of failed GitHub API calls by project",
+                )
+                .namespace(namespace),
+                &["project"],
+            )?,
+            releases_cache_hits: IntCounter::with_opts(
+                Opts::new("releases_cache_hits_total", "Number of releases served from the cache").namespace(namespace),
+            )?,
+            releases_cache_misses: IntCounter::with_opts(
+                Opts::new("releases_cache_misses_total", "Number of releases cache refreshes").namespace(namespace),
+            )?,
+            logins: IntCounterVec::new(
+                Opts::new("logins_total", "Number of logins by result").namespace(namespace),
+                &["result"],
+            )?,
+        })
+    }
+}
+
+/// Metrics not registered in any registry
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::build("api").expect("invalid metrics definition")
+    }
+}
+
+/// Database pool state, read on each scrape
+pub struct PoolCollector {
+    pool: MysqlPool,
+    connections: IntGauge,
+    idle_connections: IntGauge,
+}
+
+impl PoolCollector {
+    pub fn new(namespace: &str, pool: MysqlPool) -> Result<Self, prometheus::Error> {
+        Ok(Self {
+            pool,
+            connections: IntGauge::with_opts(
+                Opts::new("db_pool_connections", "Number of database connections").namespace(namespace),
+            )?,
+            idle_connections: IntGauge::with_opts(
+                Opts::new("db_pool_idle_connections", "Number of idle database connections").namespace(namespace),
+            )?,
+        })
+    }
+}
+
+impl Collector for PoolCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.connections
+            .desc()
+            .into_iter()
+            .chain(self.idle_connections.desc())
+            .collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let state = self.pool.state();
+        self.connections.set(state.connections as i64);
+        self.idle_connections.set(state.idle_connections as i64);
+
+        let mut families = self.connections.collect();
+        families.extend(self.idle_connections.collect());
+        families
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use actix_web::{test, web, App};
+
+    #[actix_rt::test]
+    async fn test_exposed_on_metrics() {
+        let registry = Registry::new();
+        let metrics = Metrics::new("api", &registry).unwrap();
+        registry
+            .register(Box::new(PoolCollector::new("api", db::lazy_pool()).unwrap()))
+            .unwrap();
+
+        // Vectors are only exported once a label value has been used
+        metrics
+            .github_fetch_duration
+            .with_label_values(&["actix-web"])
+            .observe(0.2);
+        metrics.github_fetch_failures.with_label_values(&["actix-web"]).inc();
+        metrics.logins.with_label_values(&["success"]).inc();
+        metrics.releases_cache_misses.inc();
+        metrics.db_pool_wait.observe(0.001);
+
+        let mut app = test::init_service(
+            App::new()
+                .data(registry)
+                .route("/metrics", web::get().to(crate::handlers::metrics)),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let body = test::read_body(test::call_service(&mut app, req).await).await;
+        let body = std::str::from_utf8(&body).unwrap();
+
+        for line in [
+            "api_db_pool_connections 0",
+            "api_db_pool_idle_connections 0",
+            "api_db_pool_wait_seconds_count 1",
+            "api_db_pool_timeouts_total 0",
+            "api_db_block_queue_seconds_count 0",
+            "api_github_fetch_duration_seconds_count{project=\"actix-web\"} 1",
+            "api_github_fetch_failures_total{project=\"actix-web\"} 1",
+            "api_releases_cache_hits_total 0",
+            "api_releases_cache_misses_total 1",
+            "api_logins_total{result=\"success\"} 1",
+            "api_ws_active_sessions 0",
+        ] {
+            assert!(body.lines().any(|l| l == line), "{} not found in:\n{}", line, body);
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Access log middleware module
+//!
+//! One record per request is logged on the `access_log` target once the response body has been sent,
+//! with method, route pattern, status, bytes, latency, request ID and user ID as structured fields.
+//! Requests slower than the threshold of their route are logged as warnings.
+//!
+//! The latency is also observed in the `http_route_duration_seconds` histogram.
+
+use crate::metrics::Metrics;
+use crate::middlewares::auth::UserId;
+use crate::middlewares::request_id::REQUEST_ID_HEADER;
+use actix_http::body::{BodySize, MessageBody, ResponseBody};
+use actix_http::http::header::{HeaderName, HeaderValue};
+use actix_service::{Service, Transform};
+use actix_web::web::Bytes;
+use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error};
+use color_eyre::Result;
+use futures::future::{ok, Ready};
+use futures::Future;
+use log::kv::ToValue;
+use log::{Level, Record};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Default slow request threshold
+const DEFAULT_SLOW_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Access log middleware
+pub struct AccessLog {
+    config: AccessLogConfig,
+}
+
+#[derive(Clone)]
+struct AccessLogConfig {
+    metrics: Metrics,
+    slow_threshold: Duration,
+    /// Slow thresholds by route pattern
+    route_thresholds: HashMap<String, Duration>,
+}
+
+impl AccessLog {
+    pub fn new(metrics: Metrics) -> Self {
+        Self {
+            config: AccessLogConfig {
+                metrics,
+                slow_threshold: DEFAULT_SLOW_THRESHOLD,
+                route_thresholds: HashMap::new(),
+            },
+        }
+    }
+
+    /// Sets the default slow request threshold (1s by default)
+    pub fn slow_threshold(mut self, threshold: Duration) -> Self {
+        self.config.slow_threshold = threshold;
+        self
+    }
+
+    /// Sets the slow request threshold of a route pattern (`/v1/users/{id}` for example)
+    pub fn route_threshold(mut self, route: &str, threshold: Duration) -> Self {
+        self.config.route_thresholds.insert(route.to_owned(), threshold);
+        self
+    }
+}
+
+impl AccessLogConfig {
+    /// Returns the slow request threshold of a route pattern
+    fn threshold(&self, route: &str) -> Duration {
+        self.route_thresholds.get(route).copied().unwrap_or(self.slow_threshold)
+    }
+}
+
+/// Returns the level of a request log, requests slower than `threshold` being logged as warnings
+fn level(latency: Duration, threshold: Duration) -> Level {
+    if latency > threshold {
+        Level::Warn
+    } else {
+        Level::Info
+    }
+}
+
+/// Parses route thresholds in milliseconds like `/v1/users/export=5000,/github-page=2000`
+pub fn parse_route_thresholds(s: &str) -> Result<Vec<(String, Duration)>, String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|threshold| !threshold.is_empty())
+        .map(|threshold| {
+            let (route, ms) = threshold
+                .rsplit_once('=')
+                .ok_or_else(|| format!("invalid route threshold: {}", threshold))?;
+            let ms = ms
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid route threshold: {}", threshold))?;
+            Ok((route.trim().to_owned(), Duration::from_millis(ms)))
+        })
+        .collect()
+}
+
+impl<S, B> Transform<S> for AccessLog
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + Unpin + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<AccessLogBody<B>>;
+    type Error = Error;
+    type Transform = AccessLogMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AccessLogMiddleware {
+            service,
+            config: Rc::new(self.config.clone()),
+        })
+    }
+}
+
+pub struct AccessLogMiddleware<S> {
+    service: S,
+    config: Rc<AccessLogConfig>,
+}
+
+impl<S, B> Service for AccessLogMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + Unpin + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<AccessLogBody<B>>;
+    type Error = Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        let method = req.method().to_string();
+        let route = req.match_pattern().unwrap_or_else(|| "default".to_owned());
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned);
+        let threshold = self.config.threshold(&route);
+        let config = self.config.clone();
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let mut res = fut.await?;
+
+            // Processing time until the response head
+            let elapsed_sec = start.elapsed().as_micros() as f32 / 1_000_000f32;
+            if let Ok(value) = HeaderValue::from_str(&format!("{}", elapsed_sec)) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static("x-process-time-s"), value);
+            }
+
+            let entry = Entry {
+                method,
+                route,
+                status: res.status().as_u16(),
+                bytes: 0,
+                request_id,
+                user_id: res.request().extensions().get::<UserId>().map(|id| id.0.clone()),
+                start,
+                threshold,
+                config,
+            };
+
+            Ok(res.map_body(move |_, body| ResponseBody::Body(AccessLogBody { body, entry })))
+        })
+    }
+}
+
+/// Access log entry, logged when dropped
+struct Entry {
+    method: String,
+    route: String,
+    status: u16,
+    bytes: u64,
+    request_id: Option<String>,
+    user_id: Option<String>,
+    start: Instant,
+    threshold: Duration,
+    config: Rc<AccessLogConfig>,
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        let latency = self.start.elapsed();
+        self.config
+            .metrics
+            .http_route_duration
+            .with_label_values(&[&self.method, &self.route, &self.status.to_string()])
+            .observe(latency.as_secs_f64());
+
+        let level = level(latency, self.threshold);
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let fields = [
+            ("method", self.method.to_value()),
+            ("route", self.route.to_value()),
+            ("status", self.status.to_value()),
+            ("bytes", self.bytes.to_value()),
+            ("latency_ms", latency_ms.to_value()),
+            ("request_id", self.request_id.as_deref().unwrap_or_default().to_value()),
+            ("user_id", self.user_id.as_deref().unwrap_or_default().to_value()),
+        ];
+
+        log::logger().log(
+            &Record::builder()
+                .args(format_args!(
+                    "{} {} {} {:.3}ms{}",
+                    self.method,
+                    self.route,
+                    self.status,
+                    latency_ms,
+                    if level == Level::Warn { " (slow request)" } else { "" }
+                ))
+                .level(level)
+                .target("access_log")
+                .module_path_static(Some(module_path!()))
+                .file_static(Some(file!()))
+                .key_values(&fields)
+                .build(),
+        );
+    }
+}
+
+/// Response body counting sent bytes
+pub struct AccessLogBody<B> {
+    body: ResponseBody<B>,
+    entry: Entry,
+}
+
+impl<B: MessageBody + Unpin> MessageBody for AccessLogBody<B> {
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Error>>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.body).poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.entry.bytes += chunk.len() as u64;
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            poll => poll,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_route_thresholds() {
+        assert_eq!(parse_route_thresholds(""), Ok(Vec::new()));
+        assert_eq!(
+            parse_route_thresholds(" /v1/users/export = 5000 ,, /github-page=2000"),
+            Ok(vec![
+                ("/v1/users/export".to_owned(), Duration::from_secs(5)),
+                ("/github-page".to_owned(), Duration::from_secs(2)),
+            ])
+        );
+
+        // Only the last `=` separates the route from the threshold
+        assert_eq!(
+            parse_route_thresholds("/search/{query=all}=1500"),
+            Ok(vec![("/search/{query=all}".to_owned(), Duration::from_millis(1500))])
+        );
+
+        assert!(parse_route_thresholds("/github-page").is_err());
+        assert!(parse_route_thresholds("/github-page=").is_err());
+        assert!(parse_route_thresholds("/github-page=2s").is_err());
+        assert!(parse_route_thresholds("/github-page=-1").is_err());
+        assert!(parse_route_thresholds("/github-page=2000,/v1/users").is_err());
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let access_log = AccessLog::new(Metrics::default())
+            .slow_threshold(Duration::from_millis(500))
+            .route_threshold("/v1/users/export", Duration::from_secs(5));
+
+        assert_eq!(access_log.config.threshold("/v1/users/export"), Duration::from_secs(5));
+        assert_eq!(access_log.config.threshold("/v1/users"), Duration::from_millis(500));
+        assert_eq!(
+            AccessLog::new(Metrics::default()).config.threshold("/v1/users"),
+            DEFAULT_SLOW_THRESHOLD
+        );
+    }
+
+    #[test]
+    fn test_level() {
+        let threshold = Duration::from_millis(500);
+        assert_eq!(level(Duration::from_millis(20), threshold), Level::Info);
+        assert_eq!(level(threshold, threshold), Level::Info);
+        assert_eq!(level(Duration::from_millis(501), threshold), Level::Warn);
+    }
+}
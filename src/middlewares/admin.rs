@@ -0,0 +1,318 @@
+//! Operational endpoints guard middleware module
+//!
+//! Requests are rejected with a 403 when the client IP is not in the allow-list,
+//! and with a 401 when basic auth credentials are configured and do not match.
+//! An empty allow-list only accepts loopback clients (`127.0.0.1` and `::1`).
+
+use crate::errors::AppError;
+use actix_service::{Service, Transform};
+use actix_web::http::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error};
+use color_eyre::Result;
+use futures::future::{ok, Either, Ready};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+
+/// IP network like `10.0.0.0/8`, or a single address
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// Returns `true` if `ip` is in the network
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(addr), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(addr) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(addr), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(addr) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| format!("invalid IP network: {}", s))?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or_else(|| format!("invalid IP network: {}", s))?,
+            None => max_prefix,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+/// Parses a list of IP networks like `127.0.0.1,10.0.0.0/8,::1`
+pub fn parse_networks(s: &str) -> Result<Vec<IpNetwork>, String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|network| !network.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// Operational endpoints guard middleware
+#[derive(Clone, Default)]
+pub struct AdminGuard {
+    config: AdminGuardConfig,
+}
+
+#[derive(Clone, Default)]
+struct AdminGuardConfig {
+    allowed_networks: Vec<IpNetwork>,
+    /// SHA-256 of the expected `Authorization` header, compared in constant time
+    authorization: Option<Vec<u8>>,
+}
+
+impl AdminGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accepts clients in `network` (and no longer only loopback clients)
+    pub fn allow(mut self, network: IpNetwork) -> Self {
+        self.config.allowed_networks.push(network);
+        self
+    }
+
+    /// Requires basic auth `credentials` (`user:password`)
+    pub fn basic_auth(mut self, credentials: &str) -> Self {
+        let header = format!("Basic {}", base64::encode(credentials));
+        self.config.authorization = Some(Sha256::digest(header.as_bytes()).to_vec());
+        self
+    }
+}
+
+impl AdminGuardConfig {
+    fn is_allowed(&self, req: &ServiceRequest) -> bool {
+        // The peer address is used on purpose, forwarded headers can be forged by clients
+        match req.peer_addr().map(|addr| addr.ip()) {
+            Some(ip) if self.allowed_networks.is_empty() => ip.is_loopback(),
+            Some(ip) => self.allowed_networks.iter().any(|network| network.contains(ip)),
+            None => false,
+        }
+    }
+
+    fn is_authenticated(&self, req: &ServiceRequest) -> bool {
+        match &self.authorization {
+            Some(expected) => {
+                let header = req.headers().get(AUTHORIZATION).map(HeaderValue::as_bytes);
+                let digest = Sha256::digest(header.unwrap_or_default());
+                digest.iter().zip(expected).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+            }
+            None => true,
+        }
+    }
+}
+
+impl<S, B> Transform<S> for AdminGuard
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AdminGuardMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AdminGuardMiddleware {
+            service,
+            config: Rc::new(self.config.clone()),
+        })
+    }
+}
+
+pub struct AdminGuardMiddleware<S> {
+    service: S,
+    config: Rc<AdminGuardConfig>,
+}
+
+impl<S, B> Service for AdminGuardMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if !self.config.is_allowed(&req) {
+            let res = req.error_response(AppError::Forbidden {
+                message: "Forbidden".to_owned(),
+            });
+            return Either::Right(ok(res.map_body(|_, body| body.into_body())));
+        }
+        if !self.config.is_authenticated(&req) {
+            let mut res = req.error_response(AppError::Unauthorized);
+            res.headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Basic realm=\"admin\""));
+            return Either::Right(ok(res.map_body(|_, body| body.into_body())));
+        }
+        Either::Left(self.service.call(req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse};
+    use std::net::SocketAddr;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn test_ip_network() {
+        let network: IpNetwork = "10.0.0.0/8".parse().unwrap();
+        assert!(network.contains(ip("10.0.0.1")));
+        assert!(network.contains(ip("10.255.255.255")));
+        assert!(!network.contains(ip("11.0.0.1")));
+        assert!(!network.contains(ip("::ffff:10.0.0.1")));
+
+        // Single addresses
+        let network: IpNetwork = "192.168.1.10".parse().unwrap();
+        assert_eq!(network, "192.168.1.10/32".parse().unwrap());
+        assert!(network.contains(ip("192.168.1.10")));
+        assert!(!network.contains(ip("192.168.1.11")));
+
+        let network: IpNetwork = "::1".parse().unwrap();
+        assert!(network.contains(ip("::1")));
+        assert!(!network.contains(ip("127.0.0.1")));
+
+        let network: IpNetwork = "fd00::/8".parse().unwrap();
+        assert!(network.contains(ip("fd12:3456::1")));
+        assert!(!network.contains(ip("fe80::1")));
+
+        // Any address of the same version
+        let network: IpNetwork = "0.0.0.0/0".parse().unwrap();
+        assert!(network.contains(ip("8.8.8.8")));
+        assert!(!network.contains(ip("2001:db8::1")));
+        let network: IpNetwork = "::/0".parse().unwrap();
+        assert!(network.contains(ip("2001:db8::1")));
+        assert!(!network.contains(ip("8.8.8.8")));
+
+        for invalid in [
+            "",
+            "localhost",
+            "10.0.0.0/",
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/-1",
+            "10.0.0/8",
+        ] {
+            assert!(invalid.parse::<IpNetwork>().is_err(), "{} is valid", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_networks() {
+        assert_eq!(parse_networks(""), Ok(Vec::new()));
+        assert_eq!(
+            parse_networks(" 127.0.0.1, ,10.0.0.0/8,::1 "),
+            Ok(vec![
+                "127.0.0.1".parse().unwrap(),
+                "10.0.0.0/8".parse().unwrap(),
+                "::1".parse().unwrap(),
+            ])
+        );
+        assert!(parse_networks("127.0.0.1,invalid").is_err());
+    }
+
+    /// Calls a guarded resource from `peer` with an optional `Authorization` header
+    async fn call(guard: AdminGuard, peer: &str, authorization: Option<&str>) -> (StatusCode, Option<String>) {
+        let mut app = test::init_service(
+            App::new().service(
+                web::resource("/metrics")
+                    .wrap(guard)
+                    .route(web::get().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let mut req = test::TestRequest::get()
+            .uri("/metrics")
+            .peer_addr(peer.parse::<SocketAddr>().unwrap());
+        if let Some(authorization) = authorization {
+            req = req.header(AUTHORIZATION, authorization);
+        }
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        let authenticate = resp
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .map(|value| value.to_str().unwrap().to_owned());
+        (resp.status(), authenticate)
+    }
+
+    #[actix_rt::test]
+    async fn test_allow_list() {
+        // Loopback clients only by default
+        assert_eq!(call(AdminGuard::new(), "127.0.0.1:4000", None).await.0, StatusCode::OK);
+        assert_eq!(call(AdminGuard::new(), "[::1]:4000", None).await.0, StatusCode::OK);
+        assert_eq!(
+            call(AdminGuard::new(), "10.0.0.1:4000", None).await.0,
+            StatusCode::FORBIDDEN
+        );
+
+        let guard = AdminGuard::new().allow("10.0.0.0/8".parse().unwrap());
+        assert_eq!(call(guard.clone(), "10.1.2.3:4000", None).await.0, StatusCode::OK);
+        assert_eq!(
+            call(guard.clone(), "192.168.1.1:4000", None).await.0,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(call(guard, "127.0.0.1:4000", None).await.0, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn test_basic_auth() {
+        let guard = AdminGuard::new()
+            .allow("0.0.0.0/0".parse().unwrap())
+            .basic_auth("admin:secret");
+        let realm = Some("Basic realm=\"admin\"".to_owned());
+
+        // "admin:secret" and "admin:wrong"
+        let (status, authenticate) = call(guard.clone(), "8.8.8.8:4000", Some("Basic YWRtaW46c2VjcmV0")).await;
+        assert_eq!((status, authenticate), (StatusCode::OK, None));
+        let (status, authenticate) = call(guard.clone(), "8.8.8.8:4000", Some("Basic YWRtaW46d3Jvbmc=")).await;
+        assert_eq!((status, authenticate), (StatusCode::UNAUTHORIZED, realm.clone()));
+        let (status, authenticate) = call(guard.clone(), "8.8.8.8:4000", None).await;
+        assert_eq!((status, authenticate), (StatusCode::UNAUTHORIZED, realm));
+
+        // The allow-list is checked first
+        let guard = AdminGuard::new().basic_auth("admin:secret");
+        let (status, _) = call(guard, "8.8.8.8:4000", Some("Basic YWRtaW46c2VjcmV0")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+}
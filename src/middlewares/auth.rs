@@ -1,5 +1,6 @@
 //! JWT middleware module
 
+use crate::errors::AppError;
 use crate::models::{auth, user::User};
 use crate::AppState;
 use crate::{db, db::MysqlPool};
@@ -7,9 +8,8 @@ use actix_service::{Service, Transform};
 use actix_web::{
     dev::{ServiceRequest, ServiceResponse},
     http::Method,
-    http::StatusCode,
     web::Data,
-    Error, HttpResponse,
+    Error, HttpMessage,
 };
 use color_eyre::Result;
 use futures::{
@@ -21,6 +21,10 @@ use std::task::{Context, Poll};
 
 const AUTHORIZATION: &str = "Authorization";
 
+/// ID of the authenticated user, stored in request extensions
+#[derive(Debug, Clone)]
+pub struct UserId(pub String);
+
 pub struct Authentication;
 
 impl<S, B> Transform<S> for Authentication
@@ -63,6 +67,7 @@ where
 
     fn call(&mut self, req: ServiceRequest) -> Self::Future {
         let mut auth_success: bool = false;
+        let mut user_id = None;
 
         if Method::OPTIONS == *req.method() {
             auth_success = true;
@@ -76,8 +81,9 @@ where
                             if let Some(pool) = req.app_data::<Data<MysqlPool>>() {
                                 if let Ok(conn) = db::mysql_pool_handler(pool.clone()) {
                                     let user = User::get_by_id(&conn, token_data.user_id);
-                                    if user.is_ok() {
+                                    if let Ok(user) = user {
                                         auth_success = true;
+                                        user_id = Some(user.id);
                                     }
                                 }
                             }
@@ -90,23 +96,18 @@ where
         }
 
         if auth_success {
+            if let Some(user_id) = user_id {
+                req.extensions_mut().insert(UserId(user_id));
+            }
             let fut = self.service.call(req);
             Box::pin(async move {
                 let res = fut.await?;
                 Ok(res)
             })
         } else {
-            Box::pin(async move {
-                Ok(req.into_response(
-                    HttpResponse::Unauthorized()
-                        .json(crate::errors::AppErrorMessage {
-                            code: StatusCode::UNAUTHORIZED.as_u16(),
-                            error: "Unauthorized".to_owned(),
-                            message: "Unauthorized".to_owned(),
-                        })
-                        .into_body(),
-                ))
-            })
+            // The body is rendered by the `ErrorRenderer` middleware
+            let res = req.error_response(AppError::Unauthorized);
+            Box::pin(async move { Ok(res.map_body(|_, body| body.into_body())) })
         }
     }
 }
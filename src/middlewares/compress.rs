@@ -0,0 +1,518 @@
+//! Compression middleware module
+//!
+//! Responses are compressed with zstd, brotli or gzip depending on the `Accept-Encoding` request header.
+//! Are not compressed:
+//! - responses of excluded paths (and their sub-paths),
+//! - responses with a `Content-Encoding` header (`Content-Encoding: identity` disables compression for a handler),
+//! - responses smaller than the minimum size,
+//! - `101 Switching Protocols`, `204 No Content` and `304 Not Modified` responses.
+//!
+//! Streamed bodies are flushed after each chunk so that clients receive data as soon as it is produced.
+
+use actix_http::body::{BodySize, MessageBody, ResponseBody};
+use actix_http::http::header::{self, HeaderValue};
+use actix_http::http::StatusCode;
+use actix_service::{Service, Transform};
+use actix_web::web::Bytes;
+use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error};
+use color_eyre::Result;
+use futures::future::{ok, Ready};
+use futures::Future;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+/// Default minimum body size to compress (in bytes)
+const DEFAULT_MIN_SIZE: usize = 1024;
+
+/// Supported encodings, in order of preference
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Zstd,
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    const ALL: [Encoding; 3] = [Self::Zstd, Self::Brotli, Self::Gzip];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Zstd => "zstd",
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+        }
+    }
+
+    /// Chooses the encoding with the highest quality in an `Accept-Encoding` header value.
+    /// Encodings with the same quality are chosen in order of preference.
+    pub fn negotiate(accept_encoding: &str) -> Option<Self> {
+        let qualities: Vec<(&str, f32)> = accept_encoding
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';').map(str::trim);
+                let coding = parts.next().filter(|coding| !coding.is_empty())?;
+                let quality = parts
+                    .find_map(|param| param.strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse().ok())?;
+                Some((coding, quality))
+            })
+            .collect();
+
+        // An encoding refused with `q=0` is not accepted through the `*` wildcard
+        let quality = |encoding: Encoding| {
+            qualities
+                .iter()
+                .find(|(coding, _)| coding.eq_ignore_ascii_case(encoding.as_str()))
+                .or_else(|| qualities.iter().find(|(coding, _)| *coding == "*"))
+                .map(|(_, quality)| *quality)
+                .filter(|quality| *quality > 0.0)
+        };
+
+        let mut best: Option<(Self, f32)> = None;
+        for encoding in Self::ALL.iter().copied() {
+            match (quality(encoding), best) {
+                (Some(q), Some((_, best_q))) if q <= best_q => (),
+                (Some(q), _) => best = Some((encoding, q)),
+                (None, _) => (),
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Compress {
+    config: CompressConfig,
+}
+
+#[derive(Clone)]
+struct CompressConfig {
+    min_size: usize,
+    excluded_paths: Vec<String>,
+}
+
+impl Default for CompressConfig {
+    fn default() -> Self {
+        Self {
+            min_size: DEFAULT_MIN_SIZE,
+            excluded_paths: Vec::new(),
+        }
+    }
+}
+
+impl Compress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bodies smaller than this size (in bytes) are not compressed
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.config.min_size = min_size;
+        self
+    }
+
+    /// Disables compression for a path and its sub-paths
+    pub fn exclude(mut self, path: &str) -> Self {
+        self.config.excluded_paths.push(path.trim_end_matches('/').to_owned());
+        self
+    }
+}
+
+impl CompressConfig {
+    fn is_excluded(&self, path: &str) -> bool {
+        self.excluded_paths
+            .iter()
+            .any(|excluded| match path.strip_prefix(excluded.as_str()) {
+                Some(rest) => rest.is_empty() || rest.starts_with('/'),
+                None => false,
+            })
+    }
+}
+
+impl<S, B> Transform<S> for Compress
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + Unpin + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<Compressed<B>>;
+    type Error = Error;
+    type Transform = CompressMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CompressMiddleware {
+            service,
+            config: Rc::new(self.config.clone()),
+        })
+    }
+}
+
+pub struct CompressMiddleware<S> {
+    service: S,
+    config: Rc<CompressConfig>,
+}
+
+impl<S, B> Service for CompressMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + Unpin + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<Compressed<B>>;
+    type Error = Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let excluded = self.config.is_excluded(req.path());
+        let encoding = if excluded {
+            None
+        } else {
+            req.headers()
+                .get(header::ACCEPT_ENCODING)
+                .and_then(|accept_encoding| accept_encoding.to_str().ok())
+                .and_then(Encoding::negotiate)
+        };
+        let config = self.config.clone();
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = fut.await?;
+
+            Ok(res.map_body(move |head, body| {
+                let compressible = !matches!(
+                    head.status,
+                    StatusCode::SWITCHING_PROTOCOLS | StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED
+                ) && !head.headers().contains_key(header::CONTENT_ENCODING)
+                    && match body.size() {
+                        BodySize::Sized(size) => size as usize >= config.min_size,
+                        BodySize::Stream => true,
+                        BodySize::None | BodySize::Empty => false,
+                    };
+                if compressible && !excluded {
+                    head.headers_mut()
+                        .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+                }
+
+                let writer = match encoding {
+                    Some(encoding) if compressible => match Writer::new(encoding) {
+                        Ok(writer) => {
+                            head.headers_mut()
+                                .insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+                            head.headers_mut().remove(header::CONTENT_LENGTH);
+                            Some(writer)
+                        }
+                        Err(e) => {
+                            error!("Compression: failed to create {} encoder: {}", encoding.as_str(), e);
+                            None
+                        }
+                    },
+                    _ => None,
+                };
+
+                ResponseBody::Body(Compressed {
+                    flush: body.size() == BodySize::Stream,
+                    body,
+                    writer,
+                    eof: false,
+                })
+            }))
+        })
+    }
+}
+
+/// Compressor writing into a buffer
+enum Writer {
+    Zstd(zstd::stream::write::Encoder<Vec<u8>>),
+    Brotli(brotli2::write::BrotliEncoder<Vec<u8>>),
+    Gzip(flate2::write::GzEncoder<Vec<u8>>),
+}
+
+impl Writer {
+    fn new(encoding: Encoding) -> io::Result<Self> {
+        Ok(match encoding {
+            Encoding::Zstd => Self::Zstd(zstd::stream::write::Encoder::new(Vec::new(), 3)?),
+            Encoding::Brotli => Self::Brotli(brotli2::write::BrotliEncoder::new(Vec::new(), 5)),
+            Encoding::Gzip => Self::Gzip(flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            )),
+        })
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Zstd(encoder) => encoder.write_all(data),
+            Self::Brotli(encoder) => encoder.write_all(data),
+            Self::Gzip(encoder) => encoder.write_all(data),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Zstd(encoder) => encoder.flush(),
+            Self::Brotli(encoder) => encoder.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+        }
+    }
+
+    /// Takes the compressed data available
+    fn take(&mut self) -> Bytes {
+        let buf = match self {
+            Self::Zstd(encoder) => encoder.get_mut(),
+            Self::Brotli(encoder) => encoder.get_mut(),
+            Self::Gzip(encoder) => encoder.get_mut(),
+        };
+        Bytes::from(std::mem::take(buf))
+    }
+
+    /// Terminates the compression and returns the remaining data
+    fn finish(self) -> io::Result<Bytes> {
+        let buf = match self {
+            Self::Zstd(encoder) => encoder.finish()?,
+            Self::Brotli(encoder) => encoder.finish()?,
+            Self::Gzip(encoder) => encoder.finish()?,
+        };
+        Ok(Bytes::from(buf))
+    }
+}
+
+/// Response body, compressed if a writer is set
+pub struct Compressed<B> {
+    body: ResponseBody<B>,
+    writer: Option<Writer>,
+    /// Flush the compressor after each chunk (streamed bodies)
+    flush: bool,
+    eof: bool,
+}
+
+impl<B: MessageBody + Unpin> MessageBody for Compressed<B> {
+    fn size(&self) -> BodySize {
+        match self.writer {
+            Some(_) => BodySize::Stream,
+            None => self.body.size(),
+        }
+    }
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Error>>> {
+        let this = self.get_mut();
+        if this.eof {
+            return Poll::Ready(None);
+        }
+
+        let writer = match &mut this.writer {
+            Some(writer) => writer,
+            None => return Pin::new(&mut this.body).poll_next(cx),
+        };
+
+        loop {
+            match Pin::new(&mut this.body).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    writer.write_all(&chunk)?;
+                    if this.flush {
+                        writer.flush()?;
+                    }
+                    let data = writer.take();
+                    if !data.is_empty() {
+                        return Poll::Ready(Some(Ok(data)));
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    this.eof = true;
+                    let mut data = writer.take().to_vec();
+                    if let Some(writer) = this.writer.take() {
+                        data.extend_from_slice(&writer.finish()?);
+                    }
+                    return Poll::Ready(Some(Ok(Bytes::from(data))));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_http::body::BodyStream;
+    use actix_web::http::HeaderMap;
+    use actix_web::{test, web, App, HttpResponse};
+    use std::io::Read;
+
+    const BODY: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ";
+
+    fn large_body() -> String {
+        BODY.repeat(20)
+    }
+
+    /// Calls `uri` and returns the response headers and the decompressed body
+    async fn get(uri: &str, accept_encoding: &str) -> (HeaderMap, String) {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(Compress::new().min_size(100).exclude("/excluded/"))
+                .route("/small", web::get().to(|| HttpResponse::Ok().body(BODY)))
+                .route("/large", web::get().to(|| HttpResponse::Ok().body(large_body())))
+                .route(
+                    "/excluded/large",
+                    web::get().to(|| HttpResponse::Ok().body(large_body())),
+                )
+                .route(
+                    "/identity",
+                    web::get().to(|| {
+                        HttpResponse::Ok()
+                            .header(header::CONTENT_ENCODING, "identity")
+                            .body(large_body())
+                    }),
+                )
+                .route(
+                    "/stream",
+                    web::get().to(|| {
+                        let chunks = (0..20).map(|_| Ok::<_, Error>(Bytes::from_static(BODY.as_bytes())));
+                        HttpResponse::Ok().streaming(futures::stream::iter(chunks))
+                    }),
+                ),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .header(header::ACCEPT_ENCODING, accept_encoding)
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        let headers = resp.headers().clone();
+        let body = test::read_body(resp).await;
+
+        let mut decoded = String::new();
+        match headers
+            .get(header::CONTENT_ENCODING)
+            .map(|encoding| encoding.to_str().unwrap())
+        {
+            Some("zstd") => decoded = String::from_utf8(zstd::decode_all(&body[..]).unwrap()).unwrap(),
+            Some("br") => {
+                brotli2::read::BrotliDecoder::new(&body[..])
+                    .read_to_string(&mut decoded)
+                    .unwrap();
+            }
+            Some("gzip") => {
+                flate2::read::GzDecoder::new(&body[..])
+                    .read_to_string(&mut decoded)
+                    .unwrap();
+            }
+            _ => decoded = String::from_utf8(body.to_vec()).unwrap(),
+        }
+        (headers, decoded)
+    }
+
+    fn content_encoding(headers: &HeaderMap) -> Option<&str> {
+        headers
+            .get(header::CONTENT_ENCODING)
+            .map(|encoding| encoding.to_str().unwrap())
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Encoding::negotiate("gzip, deflate, br, zstd"), Some(Encoding::Zstd));
+        assert_eq!(Encoding::negotiate("gzip, br"), Some(Encoding::Brotli));
+        assert_eq!(Encoding::negotiate("GZIP"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::negotiate("gzip;q=1.0, br;q=0.8"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::negotiate("*"), Some(Encoding::Zstd));
+        assert_eq!(Encoding::negotiate("zstd;q=0, *;q=0.5"), Some(Encoding::Brotli));
+
+        assert_eq!(Encoding::negotiate("zstd;q=0, br;q=0, gzip;q=0"), None);
+        assert_eq!(Encoding::negotiate("gzip;q=invalid"), None);
+        assert_eq!(Encoding::negotiate("identity, deflate"), None);
+        assert_eq!(Encoding::negotiate(""), None);
+    }
+
+    #[actix_rt::test]
+    async fn test_encodings() {
+        for (accept_encoding, encoding) in [("zstd, br, gzip", "zstd"), ("br, gzip", "br"), ("gzip", "gzip")] {
+            let (headers, body) = get("/large", accept_encoding).await;
+            assert_eq!(content_encoding(&headers), Some(encoding));
+            assert_eq!(headers.get(header::VARY).unwrap(), "accept-encoding");
+            assert!(headers.get(header::CONTENT_LENGTH).is_none());
+            assert_eq!(body, large_body());
+        }
+
+        let (headers, body) = get("/large", "zstd;q=0, deflate").await;
+        assert_eq!(content_encoding(&headers), None);
+        assert_eq!(headers.get(header::VARY).unwrap(), "accept-encoding");
+        assert_eq!(body, large_body());
+    }
+
+    #[actix_rt::test]
+    async fn test_not_compressed() {
+        // Smaller than the minimum size
+        let (headers, body) = get("/small", "gzip").await;
+        assert_eq!(content_encoding(&headers), None);
+        assert!(headers.get(header::VARY).is_none());
+        assert_eq!(body, BODY);
+
+        // Already encoded
+        let (headers, body) = get("/identity", "gzip").await;
+        assert_eq!(content_encoding(&headers), Some("identity"));
+        assert_eq!(body, large_body());
+
+        // Excluded path
+        let (headers, body) = get("/excluded/large", "gzip").await;
+        assert_eq!(content_encoding(&headers), None);
+        assert!(headers.get(header::VARY).is_none());
+        assert_eq!(body, large_body());
+    }
+
+    #[test]
+    fn test_is_excluded() {
+        let compress = Compress::new().exclude("/metrics").exclude("/events/");
+        assert!(compress.config.is_excluded("/metrics"));
+        assert!(compress.config.is_excluded("/events"));
+        assert!(compress.config.is_excluded("/events/stream"));
+        assert!(!compress.config.is_excluded("/metrics-old"));
+        assert!(!compress.config.is_excluded("/"));
+
+        // A copy can still be configured
+        let copy = compress.clone().exclude("/health");
+        assert!(copy.config.is_excluded("/health"));
+        assert!(!compress.config.is_excluded("/health"));
+    }
+
+    #[actix_rt::test]
+    async fn test_stream() {
+        let (headers, body) = get("/stream", "gzip").await;
+        assert_eq!(content_encoding(&headers), Some("gzip"));
+        assert_eq!(body, large_body());
+
+        // Each chunk is flushed as soon as it is produced
+        let mut compressed = Compressed {
+            body: ResponseBody::Body(BodyStream::new(futures::stream::iter(vec![
+                Ok::<_, Error>(Bytes::from_static(b"first chunk")),
+                Ok(Bytes::from_static(b"second chunk")),
+            ]))),
+            writer: Some(Writer::new(Encoding::Gzip).unwrap()),
+            flush: true,
+            eof: false,
+        };
+        let mut chunks = Vec::new();
+        while let Some(chunk) = futures::future::poll_fn(|cx| Pin::new(&mut compressed).poll_next(cx)).await {
+            chunks.push(chunk.unwrap());
+        }
+        // Header and first chunk, second chunk, then the trailer
+        assert_eq!(chunks.len(), 3);
+
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&chunks.concat()[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "first chunksecond chunk");
+    }
+}
@@ -1,52 +1,48 @@
-//! Timer middleware module
+//! Errors middleware module
 
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use std::time::SystemTime;
 
-use actix_http::http::header;
 use actix_service::{Service, Transform};
+use actix_web::body::{BodySize, MessageBody};
 use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error};
 use color_eyre::Result;
 use futures::future::{ok, Ready};
 use futures::Future;
 
-// There are two steps in middleware processing.
-// 1. Middleware initialization, middleware factory gets called with
-//    next service in chain as parameter.
-// 2. Middleware's call method gets called with normal request.
-pub struct Timer;
+/// Renders 4xx and 5xx responses with `handlers::errors::render`
+///
+/// Only responses carrying an error (`AppError`, extractors errors...) or without body are rendered,
+/// bodies written by handlers (readiness report for example) are left as is.
+pub struct ErrorRenderer;
 
-// Middleware factory is `Transform` trait from actix-service crate
-// `S` - type of the next service
-// `B` - type of response's body
-impl<S, B> Transform<S> for Timer
+impl<S, B> Transform<S> for ErrorRenderer
 where
     S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
     S::Future: 'static,
-    B: 'static,
+    B: MessageBody + 'static,
 {
     type Request = ServiceRequest;
     type Response = ServiceResponse<B>;
     type Error = Error;
-    type Transform = TimerMiddleware<S>;
+    type Transform = ErrorRendererMiddleware<S>;
     type InitError = ();
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ok(TimerMiddleware { service })
+        ok(ErrorRendererMiddleware { service })
     }
 }
 
-pub struct TimerMiddleware<S> {
+pub struct ErrorRendererMiddleware<S> {
     service: S,
 }
 
-impl<S, B> Service for TimerMiddleware<S>
+impl<S, B> Service for ErrorRendererMiddleware<S>
 where
     S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
     S::Future: 'static,
-    B: 'static,
+    B: MessageBody + 'static,
 {
     type Request = ServiceRequest;
     type Response = ServiceResponse<B>;
@@ -59,23 +55,21 @@ where
     }
 
     fn call(&mut self, req: ServiceRequest) -> Self::Future {
-        let now = SystemTime::now();
         let fut = self.service.call(req);
 
         Box::pin(async move {
-            let mut res = fut.await?;
+            let res = fut.await?;
 
-            let elapsed_result = now.elapsed();
-            if let Ok(elapsed) = elapsed_result {
-                if let Ok(name) = header::HeaderName::from_lowercase(b"x-process-time-s") {
-                    let elapsed_sec = elapsed.as_micros() as f32 / 1_000_000f32;
-                    if let Ok(value) = header::HeaderValue::from_str(&format!("{}", elapsed_sec)) {
-                        res.headers_mut().insert(name, value);
-                    }
-                }
+            let is_error = res.status().is_client_error() || res.status().is_server_error();
+            let is_empty = matches!(
+                res.response().body().size(),
+                BodySize::None | BodySize::Empty | BodySize::Sized(0)
+            );
+            if is_error && (res.response().error().is_some() || is_empty) {
+                Ok(crate::handlers::errors::render(res))
+            } else {
+                Ok(res)
             }
-
-            Ok(res)
         })
     }
 }
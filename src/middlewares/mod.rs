@@ -1,5 +1,9 @@
 //! Middlewares module
 
+pub mod access_log;
+pub mod admin;
 pub mod auth;
+pub mod compress;
+pub mod errors;
 pub mod request_id;
-pub mod timer;
+pub mod telemetry;
@@ -1,16 +1,23 @@
 //! RequestId middleware module
 
+use std::cell::RefCell;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
 use actix_http::http::header;
 use actix_service::{Service, Transform};
-use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error};
+use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error, HttpMessage, HttpRequest};
 use color_eyre::Result;
 use futures::future::{ok, Ready};
 use futures::Future;
 use uuid::Uuid;
 
+/// Header used to receive and send the request ID
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Maximum length of a request ID received from a client
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
 // There are two steps in middleware processing.
 // 1. Middleware initialization, middleware factory gets called with
 //    next service in chain as parameter.
@@ -58,20 +65,80 @@ where
         self.service.poll_ready(cx)
     }
 
-    fn call(&mut self, req: ServiceRequest) -> Self::Future {
-        let fut = self.service.call(req);
+    fn call(&mut self, mut req: ServiceRequest) -> Self::Future {
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| is_valid(value))
+            .map(ToOwned::to_owned)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        // The header is also set on the request so that it can be used in access logs
+        if let Ok(value) = header::HeaderValue::from_str(&request_id) {
+            req.headers_mut()
+                .insert(header::HeaderName::from_static(REQUEST_ID_HEADER), value);
+        }
+        req.extensions_mut().insert(Id(request_id.clone()));
+
+        let fut = WithRequestId {
+            request_id: request_id.clone(),
+            fut: Box::pin(self.service.call(req)),
+        };
 
         Box::pin(async move {
             let mut res = fut.await?;
 
-            let request_id = Uuid::new_v4().to_string();
-            if let Ok(name) = header::HeaderName::from_lowercase(b"x-request-id") {
-                if let Ok(value) = header::HeaderValue::from_str(&request_id) {
-                    res.headers_mut().insert(name, value);
-                }
+            if let Ok(value) = header::HeaderValue::from_str(&request_id) {
+                res.headers_mut()
+                    .insert(header::HeaderName::from_static(REQUEST_ID_HEADER), value);
             }
 
             Ok(res)
         })
     }
 }
+
+/// Request ID stored in request extensions
+#[derive(Debug, Clone)]
+pub struct Id(String);
+
+impl Id {
+    /// Returns the request ID of a request
+    pub fn get(req: &HttpRequest) -> Option<String> {
+        req.extensions().get::<Self>().map(|id| id.0.clone())
+    }
+}
+
+/// Returns the ID of the request being processed by the current thread
+pub fn current() -> Option<String> {
+    CURRENT_REQUEST_ID.with(|current| current.borrow().clone())
+}
+
+/// Accepts IDs of printable ASCII characters only, to be safely logged and forwarded
+fn is_valid(request_id: &str) -> bool {
+    !request_id.is_empty()
+        && request_id.len() <= MAX_REQUEST_ID_LENGTH
+        && request_id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+thread_local! {
+    static CURRENT_REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Future setting the current request ID while it is polled
+struct WithRequestId<F> {
+    request_id: String,
+    fut: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for WithRequestId<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let previous = CURRENT_REQUEST_ID.with(|current| current.replace(Some(self.request_id.clone())));
+        let poll = self.fut.as_mut().poll(cx);
+        CURRENT_REQUEST_ID.with(|current| current.replace(previous));
+        poll
+    }
+}
@@ -0,0 +1,89 @@
+//! Telemetry middleware module
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::middlewares::request_id::REQUEST_ID_HEADER;
+use crate::telemetry::HeaderExtractor;
+use actix_service::{Service, Transform};
+use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error};
+use color_eyre::Result;
+use futures::future::{ok, Ready};
+use futures::Future;
+use opentelemetry::global;
+use tracing::field::Empty;
+use tracing_futures::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Opens a server span per request, child of the `traceparent` header context if any
+pub struct Telemetry;
+
+impl<S, B> Transform<S> for Telemetry
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = TelemetryMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(TelemetryMiddleware { service })
+    }
+}
+
+pub struct TelemetryMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for TelemetryMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        // Route pattern rather than path to keep a low span names cardinality
+        let route = req.match_pattern().unwrap_or_else(|| "default".to_owned());
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let span = tracing::info_span!(
+            "HTTP request",
+            otel.name = %format!("{} {}", req.method(), route),
+            otel.kind = "server",
+            http.method = %req.method(),
+            http.route = %route,
+            http.target = %req.uri(),
+            http.status_code = Empty,
+            request_id,
+        );
+        let parent = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(req.headers())));
+        span.set_parent(parent);
+
+        let fut = self.service.call(req).instrument(span.clone());
+
+        Box::pin(async move {
+            let res = fut.await?;
+            span.record("http.status_code", &res.status().as_u16());
+
+            Ok(res)
+        })
+    }
+}
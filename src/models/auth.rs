@@ -19,6 +19,12 @@ pub struct Claims {
     pub user_email: String,
 }
 
+/// Token passed as query parameter (`?token=`)
+#[derive(Deserialize)]
+pub struct TokenQuery {
+    pub token: String,
+}
+
 pub struct JWT {}
 
 impl JWT {
@@ -0,0 +1,412 @@
+//! Version drift model module
+
+use crate::models::release::{parse_version, Project, Release};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+/// Dependencies file ecosystems
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Ecosystem {
+    Cargo,
+    Go,
+    Composer,
+}
+
+/// Represents a dependency pinned in a lock file
+#[derive(Serialize, Debug, Clone)]
+pub struct Dependency {
+    pub name: String,
+    pub version: String,
+    /// Github repository (`owner/name`) if it can be inferred from the dependency source
+    pub repo: Option<String>,
+    pub ecosystem: Ecosystem,
+}
+
+/// How far behind a pinned version is
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DriftStatus {
+    UpToDate,
+    Ahead,
+    Patch,
+    Minor,
+    Major,
+    Unknown,
+}
+
+/// Version drift of a dependency compared to the latest release of its project
+#[derive(Serialize, Debug)]
+pub struct DriftEntry {
+    pub project: Project,
+    pub dependency: Dependency,
+    pub latest_tag: String,
+    pub latest_url: String,
+    pub status: DriftStatus,
+    pub major_behind: u64,
+    pub minor_behind: u64,
+    pub patch_behind: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DriftReport {
+    pub entries: Vec<DriftEntry>,
+    pub dependencies_count: usize,
+    pub generated_at: String,
+}
+
+#[derive(Deserialize)]
+struct CargoLock {
+    #[serde(default)]
+    package: Vec<CargoPackage>,
+}
+
+#[derive(Deserialize)]
+struct CargoPackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ComposerLock {
+    #[serde(default)]
+    packages: Vec<ComposerPackage>,
+    #[serde(default, rename = "packages-dev")]
+    packages_dev: Vec<ComposerPackage>,
+}
+
+#[derive(Deserialize)]
+struct ComposerPackage {
+    name: String,
+    version: String,
+    source: Option<ComposerSource>,
+}
+
+#[derive(Deserialize)]
+struct ComposerSource {
+    url: String,
+}
+
+impl Dependency {
+    /// Parses dependencies from a `Cargo.lock` file
+    pub fn from_cargo_lock(content: &str) -> Result<Vec<Self>, toml::de::Error> {
+        let lock: CargoLock = toml::from_str(content)?;
+
+        Ok(lock
+            .package
+            .into_iter()
+            .filter(|package| package.source.is_some())
+            .map(|package| Self {
+                // Registry sources (like crates.io) are the index repository, not the package one
+                repo: package
+                    .source
+                    .as_deref()
+                    .and_then(|source| source.strip_prefix("git+"))
+                    .and_then(github_repo),
+                name: package.name,
+                version: package.version,
+                ecosystem: Ecosystem::Cargo,
+            })
+            .collect())
+    }
+
+    /// Parses dependencies from a `go.mod` file
+    pub fn from_go_mod(content: &str) -> Vec<Self> {
+        let mut dependencies = Vec::new();
+        let mut in_require_block = false;
+
+        for line in content.lines() {
+            let line = line.split("//").next().unwrap_or_default().trim();
+
+            let requirement = if in_require_block {
+                if line == ")" {
+                    in_require_block = false;
+                    continue;
+                }
+                line
+            } else if line == "require (" {
+                in_require_block = true;
+                continue;
+            } else if let Some(requirement) = line.strip_prefix("require ") {
+                requirement
+            } else {
+                continue;
+            };
+
+            let mut parts = requirement.split_whitespace();
+            if let (Some(module), Some(version)) = (parts.next(), parts.next()) {
+                dependencies.push(Self {
+                    name: module.to_owned(),
+                    version: version.to_owned(),
+                    repo: github_repo(module),
+                    ecosystem: Ecosystem::Go,
+                });
+            }
+        }
+        dependencies
+    }
+
+    /// Parses dependencies from a `composer.lock` file
+    pub fn from_composer_lock(content: &str) -> Result<Vec<Self>, serde_json::Error> {
+        let lock: ComposerLock = serde_json::from_str(content)?;
+
+        Ok(lock
+            .packages
+            .into_iter()
+            .chain(lock.packages_dev)
+            .map(|package| Self {
+                repo: package.source.as_ref().and_then(|source| github_repo(&source.url)),
+                name: package.name,
+                version: package.version,
+                ecosystem: Ecosystem::Composer,
+            })
+            .collect())
+    }
+
+    /// Returns `true` if the dependency belongs to the project.
+    /// Dependencies without known repository are matched on the project repository name.
+    pub fn is_from(&self, project: &Project) -> bool {
+        match &self.repo {
+            Some(repo) => repo.eq_ignore_ascii_case(&project.repo),
+            None => {
+                let repo_name = project.repo.rsplit('/').next().unwrap_or(&project.repo);
+                self.name.eq_ignore_ascii_case(repo_name)
+            }
+        }
+    }
+}
+
+impl DriftEntry {
+    /// Compares the dependency pinned version with the latest release of the project
+    pub fn new(dependency: Dependency, release: &Release) -> Option<Self> {
+        let project = release.project.clone()?;
+
+        let (status, major_behind, minor_behind, patch_behind) =
+            match (parse_version(&dependency.version), release.version()) {
+                (Some(pinned), Some(latest)) if pinned == latest => (DriftStatus::UpToDate, 0, 0, 0),
+                (Some(pinned), Some(latest)) if pinned > latest => (DriftStatus::Ahead, 0, 0, 0),
+                (Some(pinned), Some(latest)) if pinned.major < latest.major => {
+                    (DriftStatus::Major, latest.major - pinned.major, 0, 0)
+                }
+                (Some(pinned), Some(latest)) if pinned.minor < latest.minor => {
+                    (DriftStatus::Minor, 0, latest.minor - pinned.minor, 0)
+                }
+                (Some(pinned), Some(latest)) if pinned.patch < latest.patch => {
+                    (DriftStatus::Patch, 0, 0, latest.patch - pinned.patch)
+                }
+                // Same version numbers with different pre-release or build metadata
+                (Some(_), Some(_)) => (DriftStatus::Patch, 0, 0, 0),
+                _ => (DriftStatus::Unknown, 0, 0, 0),
+            };
+
+        Some(Self {
+            project,
+            dependency,
+            latest_tag: release.tag_name.clone(),
+            latest_url: release.html_url.clone(),
+            status,
+            major_behind,
+            minor_behind,
+            patch_behind,
+        })
+    }
+}
+
+impl DriftStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UpToDate => "up_to_date",
+            Self::Ahead => "ahead",
+            Self::Patch => "patch",
+            Self::Minor => "minor",
+            Self::Major => "major",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+impl DriftReport {
+    /// Maps dependencies onto tracked projects and computes their drift, most outdated first
+    pub fn new(dependencies: Vec<Dependency>, releases: &[Release]) -> Self {
+        let dependencies_count = dependencies.len();
+        let mut entries: Vec<DriftEntry> = dependencies
+            .into_iter()
+            .filter_map(|dependency| {
+                let release = releases.iter().find(|release| match &release.project {
+                    Some(project) => dependency.is_from(project),
+                    None => false,
+                })?;
+                DriftEntry::new(dependency, release)
+            })
+            .collect();
+
+        entries.sort_by(|a, b| {
+            (b.major_behind, b.minor_behind, b.patch_behind)
+                .cmp(&(a.major_behind, a.minor_behind, a.patch_behind))
+                .then_with(|| a.project.name.cmp(&b.project.name))
+        });
+
+        Self {
+            entries,
+            dependencies_count,
+            generated_at: Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// Extracts a Github repository (`owner/name`) from a dependency source or module path:
+/// `git+https://github.com/actix/actix-web?branch=master#abc`, `https://github.com/laravel/framework.git`
+/// or `github.com/gin-gonic/gin`.
+fn github_repo(source: &str) -> Option<String> {
+    let index = source.find("github.com/")?;
+    let mut parts = source[index + "github.com/".len()..]
+        .split(['/', '?', '#'])
+        .filter(|part| !part.is_empty());
+
+    let owner = parts.next()?;
+    let name = parts.next()?.trim_end_matches(".git");
+    Some(format!("{}/{}", owner, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semver::Version;
+
+    fn version(version: &str) -> Option<Version> {
+        Some(Version::parse(version).unwrap())
+    }
+
+    #[test]
+    fn test_from_cargo_lock() {
+        let content = r#"
+            [[package]]
+            name = "actix-web"
+            version = "3.3.2"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "test-actix"
+            version = "0.1.0"
+
+            [[package]]
+            name = "rocket"
+            version = "0.5.0-dev"
+            source = "git+https://github.com/SergioBenitez/Rocket?branch=master#abc123"
+        "#;
+        let dependencies = Dependency::from_cargo_lock(content).unwrap();
+
+        // Local packages have no source
+        assert_eq!(dependencies.len(), 2);
+        assert_eq!(dependencies[0].name, "actix-web");
+        assert_eq!(dependencies[0].version, "3.3.2");
+        assert_eq!(dependencies[0].ecosystem, Ecosystem::Cargo);
+        assert_eq!(dependencies[0].repo, None);
+        assert_eq!(dependencies[1].name, "rocket");
+        assert_eq!(dependencies[1].repo.as_deref(), Some("SergioBenitez/Rocket"));
+
+        // Registry packages are matched on the project name
+        let actix_web = Project::new("Actix".to_owned(), "actix/actix-web".to_owned(), "Rust".to_owned());
+        assert!(dependencies[0].is_from(&actix_web));
+        assert!(!dependencies[1].is_from(&actix_web));
+
+        assert!(Dependency::from_cargo_lock("").unwrap().is_empty());
+        assert!(Dependency::from_cargo_lock("[[package]]\nname = 1").is_err());
+    }
+
+    #[test]
+    fn test_from_go_mod() {
+        let content = r#"
+            module github.com/owner/app
+
+            go 1.15
+
+            require github.com/gin-gonic/gin v1.6.3
+
+            require (
+                github.com/golang/protobuf v1.4.3 // indirect
+                golang.org/x/sys v0.0.0-20201021035429-f5854403a974
+                github.com/coreos/etcd v3.3.25+incompatible
+            )
+        "#;
+        let dependencies = Dependency::from_go_mod(content);
+
+        let parsed: Vec<(&str, &str, Option<&str>)> = dependencies
+            .iter()
+            .map(|d| (d.name.as_str(), d.version.as_str(), d.repo.as_deref()))
+            .collect();
+        assert_eq!(
+            parsed,
+            vec![
+                ("github.com/gin-gonic/gin", "v1.6.3", Some("gin-gonic/gin")),
+                ("github.com/golang/protobuf", "v1.4.3", Some("golang/protobuf")),
+                ("golang.org/x/sys", "v0.0.0-20201021035429-f5854403a974", None),
+                ("github.com/coreos/etcd", "v3.3.25+incompatible", Some("coreos/etcd")),
+            ]
+        );
+        assert!(dependencies.iter().all(|d| d.ecosystem == Ecosystem::Go));
+        assert!(Dependency::from_go_mod("module github.com/owner/app").is_empty());
+    }
+
+    #[test]
+    fn test_from_composer_lock() {
+        let content = r#"{
+            "packages": [
+                {
+                    "name": "laravel/framework",
+                    "version": "v8.10.0",
+                    "source": {"type": "git", "url": "https://github.com/laravel/framework.git"}
+                },
+                {"name": "local/package", "version": "dev-master"}
+            ],
+            "packages-dev": [
+                {
+                    "name": "phpunit/phpunit",
+                    "version": "9.4.2",
+                    "source": {"type": "git", "url": "https://github.com/sebastianbergmann/phpunit.git"}
+                }
+            ]
+        }"#;
+        let dependencies = Dependency::from_composer_lock(content).unwrap();
+
+        let parsed: Vec<(&str, &str, Option<&str>)> = dependencies
+            .iter()
+            .map(|d| (d.name.as_str(), d.version.as_str(), d.repo.as_deref()))
+            .collect();
+        assert_eq!(
+            parsed,
+            vec![
+                ("laravel/framework", "v8.10.0", Some("laravel/framework")),
+                ("local/package", "dev-master", None),
+                ("phpunit/phpunit", "9.4.2", Some("sebastianbergmann/phpunit")),
+            ]
+        );
+        assert!(dependencies.iter().all(|d| d.ecosystem == Ecosystem::Composer));
+        assert!(Dependency::from_composer_lock("{}").unwrap().is_empty());
+        assert!(Dependency::from_composer_lock("[").is_err());
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("1.2.3"), version("1.2.3"));
+        assert_eq!(parse_version("v1.2.3"), version("1.2.3"));
+        assert_eq!(parse_version(" v3.0.0-beta.1 "), version("3.0.0-beta.1"));
+        assert_eq!(parse_version("release-2.0"), version("2.0.0"));
+        assert_eq!(parse_version("go1.15"), version("1.15.0"));
+        assert_eq!(parse_version("v2"), version("2.0.0"));
+        assert_eq!(parse_version("v1.2-rc1"), version("1.2.0-rc1"));
+
+        // Go pseudo-versions and incompatible modules
+        assert_eq!(
+            parse_version("v0.0.0-20201021035429-f5854403a974"),
+            version("0.0.0-20201021035429-f5854403a974")
+        );
+        assert_eq!(parse_version("v3.3.25+incompatible"), version("3.3.25+incompatible"));
+        assert!(parse_version("v0.0.0-20201021035429-f5854403a974") < parse_version("v0.0.1"));
+
+        assert_eq!(parse_version("nightly"), None);
+        assert_eq!(parse_version("dev-master"), None);
+        assert_eq!(parse_version("1.2.3.4"), None);
+    }
+}
@@ -0,0 +1,137 @@
+//! Health model module
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Readiness checks settings
+#[derive(Debug, Clone)]
+pub struct HealthSettings {
+    /// Maximum duration of each check
+    pub timeout: Duration,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Health status of a component or of the whole service
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Up,
+    /// A non critical component is down
+    Degraded,
+    Down,
+}
+
+/// Result of a component check
+#[derive(Serialize, Debug)]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    /// The service is down if a critical component is down
+    pub critical: bool,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl ComponentHealth {
+    pub fn new(critical: bool, duration: Duration, result: Result<(), String>) -> Self {
+        let (status, message) = match result {
+            Ok(()) => (HealthStatus::Up, None),
+            Err(message) => (HealthStatus::Down, Some(message)),
+        };
+        Self {
+            status,
+            critical,
+            duration_ms: duration.as_millis() as u64,
+            message,
+        }
+    }
+}
+
+/// Service health with its components breakdown
+#[derive(Serialize, Debug)]
+pub struct Health {
+    pub status: HealthStatus,
+    pub components: BTreeMap<&'static str, ComponentHealth>,
+}
+
+impl Health {
+    pub fn new(components: BTreeMap<&'static str, ComponentHealth>) -> Self {
+        let down = components
+            .values()
+            .filter(|component| component.status == HealthStatus::Down);
+        let status = down.fold(HealthStatus::Up, |status, component| match status {
+            _ if component.critical => HealthStatus::Down,
+            HealthStatus::Down => HealthStatus::Down,
+            _ => HealthStatus::Degraded,
+        });
+        Self { status, components }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(critical: bool, up: bool) -> ComponentHealth {
+        let result = if up { Ok(()) } else { Err("down".to_owned()) };
+        ComponentHealth::new(critical, Duration::from_millis(1), result)
+    }
+
+    fn status(components: Vec<(&'static str, ComponentHealth)>) -> HealthStatus {
+        Health::new(components.into_iter().collect()).status
+    }
+
+    #[test]
+    fn test_status() {
+        assert_eq!(status(Vec::new()), HealthStatus::Up);
+        assert_eq!(
+            status(vec![
+                ("database", component(true, true)),
+                ("cache", component(false, true))
+            ]),
+            HealthStatus::Up
+        );
+
+        // Non critical component down
+        assert_eq!(
+            status(vec![
+                ("database", component(true, true)),
+                ("cache", component(false, false))
+            ]),
+            HealthStatus::Degraded
+        );
+
+        // Critical component down, whatever the order of the components
+        assert_eq!(
+            status(vec![("a", component(true, false)), ("b", component(false, false))]),
+            HealthStatus::Down
+        );
+        assert_eq!(
+            status(vec![("a", component(false, false)), ("b", component(true, false))]),
+            HealthStatus::Down
+        );
+        assert_eq!(
+            status(vec![("a", component(true, false)), ("b", component(true, true))]),
+            HealthStatus::Down
+        );
+    }
+
+    #[test]
+    fn test_component() {
+        let up = component(true, true);
+        assert_eq!((up.status, up.message), (HealthStatus::Up, None));
+
+        let down = ComponentHealth::new(false, Duration::from_micros(2500), Err("timed out".to_owned()));
+        assert_eq!(down.status, HealthStatus::Down);
+        assert_eq!(down.duration_ms, 2);
+        assert_eq!(down.message.as_deref(), Some("timed out"));
+    }
+}
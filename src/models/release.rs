@@ -1,12 +1,33 @@
 //! Release model module
 
+use crate::db::MysqlPool;
+use crate::metrics::Metrics;
+use crate::middlewares::request_id;
+use crate::models::repository::RepositoryStats;
+use crate::sse::{self, Broadcaster, Publish};
+use crate::telemetry::HeaderInjector;
+use crate::ws::broker::{Broker, PublishReleases};
+use crate::ws::protocol::ReleaseEvent;
+use actix::SystemService;
 use actix_web::http::StatusCode;
-use chrono::{DateTime, Duration, Utc};
-use color_eyre::Result;
-use futures::future::join_all;
-use reqwest::header::USER_AGENT;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use futures::future::{join, join_all};
+use futures::lock::Mutex;
+use opentelemetry::global;
+use pulldown_cmark::{html, Parser};
+use reqwest::header::{HeaderMap, USER_AGENT};
+use semver::Version;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::fs::File;
+use std::sync::atomic::{self, AtomicI64};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing_futures::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 pub const PROJECTS_FILE: &str = "projects.json";
 
@@ -20,6 +41,8 @@ pub struct Release {
     pub body: String,
     pub created_at: String,
     pub published_at: String,
+    #[serde(default)]
+    pub stats: Option<RepositoryStats>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -29,11 +52,77 @@ pub struct Project {
     pub language: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Deserialize, Debug)]
+pub struct FeedQuery {
+    pub language: Option<String>,
+}
+
+/// Releases sorting criteria
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleasesSort {
+    PublishedAt,
+    Semver,
+}
+
+/// Sort order
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Releases grouping criteria
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleasesGroup {
+    Language,
+}
+
+/// Query parameters used to filter, sort and group releases
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ReleasesQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<ReleasesSort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<SortOrder>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_by: Option<ReleasesGroup>,
+}
+
+/// Represents a release entry in Atom or RSS feeds
+#[derive(Debug)]
+pub struct FeedEntry {
+    pub title: String,
+    pub url: String,
+    pub language: String,
+    pub content: String,
+    pub published_at: DateTime<Utc>,
+}
+
+/// Releases cache shared between workers.
+///
+/// The cache state is protected by an async-aware mutex which is held during a refresh:
+/// concurrent requests wait for the single in-flight Github fetch instead of starting their own,
+/// and executor threads are never blocked.
+#[derive(Debug)]
 pub struct ReleasesCache {
-    pub releases: Vec<Release>,
-    pub projects: Vec<Project>,
-    pub expired_at: DateTime<Utc>,
+    state: Mutex<CachedReleases>,
+    /// Expiration date of the loaded releases (timestamp in milliseconds, 0 until loaded),
+    /// readable without waiting for a refresh
+    expired_at: AtomicI64,
+    metrics: Metrics,
+}
+
+#[derive(Debug)]
+struct CachedReleases {
+    releases: Arc<Vec<Release>>,
+    expired_at: DateTime<Utc>,
 }
 
 impl Project {
@@ -41,7 +130,7 @@ impl Project {
     pub fn new(name: String, repo: String, language: String) -> Self {
         Self { name, repo, language }
     }
-    
+
     /// Returns projects list from JSON file
     pub fn from_file(file_name: &str) -> Vec<Self> {
         match File::open(file_name) {
@@ -59,53 +148,101 @@ impl Project {
         }
     }
 
-    /// Get repository information from Github API
-    pub async fn get_info(self, github_username: &str, github_token: &str) -> Release {
-        let url = format!("https://api.github.com/repos/{}/releases/latest", self.repo);
+    /// Get repository information (latest release and statistics) from Github API.
+    /// Calls are recorded in `metrics` by project name, if any.
+    pub async fn get_info(self, github_username: &str, github_token: &str, metrics: Option<&Metrics>) -> Release {
+        let release_url = format!("https://api.github.com/repos/{}/releases/latest", self.repo);
+        let stats_url = format!("https://api.github.com/repos/{}", self.repo);
+
+        let (release, stats) = join(
+            self.fetch::<Release>(&release_url, github_username, github_token, metrics),
+            self.fetch::<RepositoryStats>(&stats_url, github_username, github_token, metrics),
+        )
+        .await;
+
+        match release {
+            Some(mut release) => {
+                release.project = Some(self);
+                release.stats = stats;
+                release
+            }
+            None => Release::new(),
+        }
+    }
+
+    /// Call Github API and deserialize the JSON response
+    async fn fetch<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        github_username: &str,
+        github_token: &str,
+        metrics: Option<&Metrics>,
+    ) -> Option<T> {
+        let started_at = Instant::now();
+        let span = tracing::info_span!(
+            "GitHub API",
+            otel.name = "GET api.github.com",
+            otel.kind = "client",
+            http.method = "GET",
+            http.url = url,
+            http.status_code = Empty,
+        );
+        let mut headers = HeaderMap::new();
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&span.context(), &mut HeaderInjector(&mut headers))
+        });
+
         let client = reqwest::Client::new();
-        let _resp = client
-            .get(&url)
+        let mut request = client
+            .get(url)
             .header(USER_AGENT, "test-actix")
-            .basic_auth(github_username, Some(github_token))
-            .send()
-            .await;
+            .headers(headers)
+            .basic_auth(github_username, Some(github_token));
+        // Correlates GitHub calls with the request which has triggered the cache refresh
+        if let Some(request_id) = request_id::current() {
+            request = request.header(request_id::REQUEST_ID_HEADER, request_id);
+        }
+        let _resp = request.send().instrument(span.clone()).await;
+        if let Ok(resp) = &_resp {
+            span.record("http.status_code", &resp.status().as_u16());
+        }
 
-        match _resp {
+        let data = match _resp {
             Err(e) => {
-                error!("Github releases: {:?}", e);
-                Release::new()
+                error!("Github API: {:?}", e);
+                None
             }
             Ok(resp) => match resp.status() {
-                StatusCode::OK => {
-                    let resp = resp.text().await;
-
-                    match resp {
+                StatusCode::OK => match resp.text().await {
+                    Err(e) => {
+                        error!("Github API: {:?}", e);
+                        None
+                    }
+                    Ok(resp) => match serde_json::from_str(&resp) {
                         Err(e) => {
-                            error!("Github releases: {:?}", e);
-                            Release::new()
-                        }
-                        Ok(resp) => {
-                            let release: Result<Release, serde_json::Error> = serde_json::from_str(&resp);
-
-                            match release {
-                                Err(e) => {
-                                    error!("Github releases: {:?}", e);
-                                    Release::new()
-                                }
-                                Ok(mut release) => {
-                                    release.project = Some(self);
-                                    release
-                                }
-                            }
+                            error!("Github API: {:?}", e);
+                            None
                         }
-                    }
-                }
+                        Ok(data) => Some(data),
+                    },
+                },
                 _ => {
-                    error!("Github API error for project {:?}", self);
-                    Release::new()
+                    error!("Github API error for project {:?} ({})", self, url);
+                    None
                 }
             },
+        };
+
+        if let Some(metrics) = metrics {
+            metrics
+                .github_fetch_duration
+                .with_label_values(&[&self.name])
+                .observe(started_at.elapsed().as_secs_f64());
+            if data.is_none() {
+                metrics.github_fetch_failures.with_label_values(&[&self.name]).inc();
+            }
         }
+        data
     }
 }
 
@@ -120,14 +257,67 @@ impl Release {
             body: String::from(""),
             created_at: String::from(""),
             published_at: String::from(""),
+            stats: None,
         }
     }
 
+    /// Returns `true` if the release project is written in `language` (case insensitive)
+    pub fn has_language(&self, language: &str) -> bool {
+        match &self.project {
+            Some(project) => project.language.eq_ignore_ascii_case(language),
+            None => false,
+        }
+    }
+
+    /// Returns the release body (Markdown) rendered as HTML
+    pub fn body_html(&self) -> String {
+        let mut content = String::new();
+        html::push_html(&mut content, Parser::new(&self.body));
+        content
+    }
+
+    /// Returns the publication date, if valid
+    pub fn published_datetime(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.published_at)
+            .ok()
+            .map(|date| date.with_timezone(&Utc))
+    }
+
+    /// Returns the semantic version of the release parsed from its tag name
+    pub fn version(&self) -> Option<Version> {
+        parse_version(&self.tag_name)
+    }
+
+    /// Returns the project language or an empty string if the release has no project
+    pub fn language(&self) -> &str {
+        self.project.as_ref().map_or("", |project| &project.language)
+    }
+
+    /// Converts the release into a feed entry.
+    /// Releases without project or with an invalid publication date are ignored.
+    pub fn to_feed_entry(&self) -> Option<FeedEntry> {
+        let project = self.project.as_ref()?;
+        let published_at = self.published_datetime()?;
+
+        Some(FeedEntry {
+            title: format!("{} {}", project.name, self.tag_name),
+            url: self.html_url.clone(),
+            language: project.language.clone(),
+            content: self.body_html(),
+            published_at,
+        })
+    }
+
     /// Get all releases from Github API async
-    pub async fn get_all(projects: Vec<Project>, github_username: &str, github_token: &str) -> Vec<Self> {
+    pub async fn get_all(
+        projects: Vec<Project>,
+        github_username: &str,
+        github_token: &str,
+        metrics: &Metrics,
+    ) -> Vec<Self> {
         let num_futures: Vec<_> = projects
             .into_iter()
-            .map(|project| project.get_info(github_username, github_token))
+            .map(|project| project.get_info(github_username, github_token, Some(metrics)))
             .collect();
 
         join_all(num_futures)
@@ -138,37 +328,456 @@ impl Release {
     }
 }
 
+impl ReleasesSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PublishedAt => "published_at",
+            Self::Semver => "semver",
+        }
+    }
+}
+
+impl SortOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Asc => "asc",
+            Self::Desc => "desc",
+        }
+    }
+}
+
+impl ReleasesQuery {
+    /// Returns the language filter, if not empty
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref().map(str::trim).filter(|l| !l.is_empty())
+    }
+
+    /// Returns the project name search, if not empty
+    pub fn search(&self) -> Option<&str> {
+        self.search.as_deref().map(str::trim).filter(|s| !s.is_empty())
+    }
+
+    /// Returns the sorting criteria (publication date by default)
+    pub fn sort(&self) -> ReleasesSort {
+        self.sort.unwrap_or(ReleasesSort::PublishedAt)
+    }
+
+    /// Returns the sort order (descending by default)
+    pub fn order(&self) -> SortOrder {
+        self.order.unwrap_or(SortOrder::Desc)
+    }
+
+    /// Returns `true` if the release matches language and search filters
+    pub fn matches(&self, release: &Release) -> bool {
+        let project = match &release.project {
+            Some(project) => project,
+            None => return false,
+        };
+        if let Some(language) = self.language() {
+            if !release.has_language(language) {
+                return false;
+            }
+        }
+        if let Some(search) = self.search() {
+            if !project.name.to_lowercase().contains(&search.to_lowercase()) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Filters and sorts releases
+    pub fn apply(&self, releases: &[Release]) -> Vec<Release> {
+        let mut releases: Vec<Release> = releases.iter().filter(|r| self.matches(r)).cloned().collect();
+
+        let order = self.order();
+        releases.sort_by(|a, b| match self.sort() {
+            ReleasesSort::PublishedAt => cmp_options(a.published_datetime(), b.published_datetime(), order),
+            ReleasesSort::Semver => cmp_options(a.version(), b.version(), order)
+                .then_with(|| cmp_options(a.published_datetime(), b.published_datetime(), SortOrder::Desc)),
+        });
+        releases
+    }
+
+    /// Filters, sorts and groups releases.
+    /// Without grouping, all releases are in a single group with an empty name.
+    pub fn apply_grouped(&self, releases: &[Release]) -> BTreeMap<String, Vec<Release>> {
+        let mut groups = BTreeMap::new();
+        for release in self.apply(releases) {
+            let key = match self.group_by {
+                Some(ReleasesGroup::Language) => release.language().to_owned(),
+                None => String::new(),
+            };
+            groups.entry(key).or_insert_with(Vec::new).push(release);
+        }
+        groups
+    }
+}
+
+/// Parses a semantic version from a tag or a pinned version.
+/// Prefixes (`v`, `go`, `release-`, ...) are ignored and missing minor or patch numbers are set to 0.
+pub fn parse_version(tag: &str) -> Option<Version> {
+    let tag = tag.trim().trim_start_matches(|c: char| !c.is_ascii_digit());
+    if let Ok(version) = Version::parse(tag) {
+        return Some(version);
+    }
+
+    let (numbers, suffix) = match tag.find(|c: char| c != '.' && !c.is_ascii_digit()) {
+        Some(index) => tag.split_at(index),
+        None => (tag, ""),
+    };
+    let mut numbers: Vec<&str> = numbers.trim_end_matches('.').split('.').collect();
+    if numbers.is_empty() || numbers.len() > 3 {
+        return None;
+    }
+    numbers.resize(3, "0");
+
+    Version::parse(&format!("{}{}", numbers.join("."), suffix)).ok()
+}
+
+/// Returns events for releases whose tag changed since the previous releases.
+/// Projects without previous release (newly tracked or never fetched successfully) have no event.
+fn release_events(previous: &[Release], releases: &[Release]) -> Vec<ReleaseEvent> {
+    releases
+        .iter()
+        .filter_map(|release| {
+            let project = release.project.as_ref()?;
+            let previous_tag_name = previous
+                .iter()
+                .find(|previous| match &previous.project {
+                    Some(p) => p.repo == project.repo,
+                    None => false,
+                })
+                .map(|previous| previous.tag_name.clone())?;
+
+            if previous_tag_name == release.tag_name {
+                return None;
+            }
+            Some(ReleaseEvent {
+                name: project.name.clone(),
+                repo: project.repo.clone(),
+                language: project.language.clone(),
+                tag_name: release.tag_name.clone(),
+                previous_tag_name,
+                url: release.html_url.clone(),
+                published_at: release.published_at.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the releases in projects order, taking the previous release of projects whose fetch failed
+fn with_previous(projects: &[Project], previous: &[Release], mut releases: Vec<Release>) -> Vec<Release> {
+    projects
+        .iter()
+        .filter_map(|project| {
+            let is_from = |release: &Release| match &release.project {
+                Some(p) => p.repo == project.repo,
+                None => false,
+            };
+            match releases.iter().position(is_from) {
+                Some(index) => Some(releases.swap_remove(index)),
+                None => previous.iter().find(|release| is_from(release)).cloned(),
+            }
+        })
+        .collect()
+}
+
+/// Compares two optional values according to the sort order, `None` values always being last
+fn cmp_options<T: Ord>(a: Option<T>, b: Option<T>, order: SortOrder) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) if order == SortOrder::Asc => a.cmp(&b),
+        (Some(a), Some(b)) => b.cmp(&a),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
 impl ReleasesCache {
-    /// Create a new cache for releases
-    pub fn new() -> Self {
+    /// Create a new cache for releases, recording hits and misses in `metrics`
+    pub fn new(metrics: Metrics) -> Self {
         Self {
-            releases: Vec::new(),
-            expired_at: Utc::now(),
-            projects: Vec::new(),
+            state: Mutex::new(CachedReleases {
+                releases: Arc::new(Vec::new()),
+                expired_at: Utc::now(),
+            }),
+            expired_at: AtomicI64::new(0),
+            metrics,
         }
     }
 
-    /// Get releases from Github
+    /// Get releases and cache expiration date.
+    /// Releases are fetched from Github if the cache is empty or expired.
+    ///
+    /// The new state is only stored once the fetch is complete, so a cancelled refresh leaves the
+    /// previous releases in place and the next caller retries. Projects whose fetch failed keep
+    /// their previous release.
     pub async fn get_releases(
-        &mut self,
-        github_api_username: String,
-        github_api_token: String,
-    ) -> (&Vec<Release>, DateTime<Utc>) {
+        &self,
+        github_api_username: &str,
+        github_api_token: &str,
+        pool: MysqlPool,
+    ) -> (Arc<Vec<Release>>, DateTime<Utc>) {
+        let mut state = self.state.lock().await;
+
         let now = Utc::now();
-        if (*self).releases.is_empty() || (*self).expired_at < now {
+        if state.releases.is_empty() || state.expired_at < now {
+            self.metrics.releases_cache_misses.inc();
             let projects = Project::from_file(PROJECTS_FILE);
 
-            (*self).releases = Release::get_all(projects.clone(), &github_api_username, &github_api_token).await;
-            (*self).expired_at = now + Duration::hours(1);
-            (*self).projects = projects;
+            let releases =
+                Release::get_all(projects.clone(), github_api_username, github_api_token, &self.metrics).await;
+            let releases = RepositoryStats::track(pool, &self.metrics, releases).await;
+            let releases = with_previous(&projects, &state.releases, releases);
+
+            // Publish new releases to WebSocket and SSE clients (not on first load)
+            if !state.releases.is_empty() {
+                let events = release_events(&state.releases, &releases);
+                for release in &events {
+                    Broadcaster::from_registry().do_send(Publish {
+                        event: sse::Event::ReleaseChanged {
+                            release: release.clone(),
+                        },
+                    });
+                }
+                if !events.is_empty() {
+                    Broker::from_registry().do_send(PublishReleases { events });
+                }
+            }
+            state.releases = Arc::new(releases);
+            state.expired_at = now + Duration::hours(1);
+            self.store_expired_at(&state);
+        } else {
+            self.metrics.releases_cache_hits.inc();
         }
-        (&(*self).releases, (*self).expired_at)
+        (state.releases.clone(), state.expired_at)
+    }
+
+    /// Returns the cache expiration date, or `None` if releases have not been loaded.
+    /// Does not wait for a refresh in progress.
+    pub fn expired_at(&self) -> Option<DateTime<Utc>> {
+        match self.expired_at.load(atomic::Ordering::Relaxed) {
+            0 => None,
+            timestamp => Some(Utc.timestamp_millis(timestamp)),
+        }
+    }
+
+    fn store_expired_at(&self, state: &CachedReleases) {
+        let timestamp = match state.releases.is_empty() {
+            true => 0,
+            false => state.expired_at.timestamp_millis(),
+        };
+        self.expired_at.store(timestamp, atomic::Ordering::Relaxed);
     }
 }
 
 // Je ne vois pas trop pourquoi Clippy le demande...
 impl Default for ReleasesCache {
     fn default() -> Self {
-        Self::new()
+        Self::new(Metrics::default())
+    }
+}
+
+#[cfg(test)]
+impl Release {
+    /// Release of a project published at `published_at` (RFC 3339)
+    pub(crate) fn fake(project: &str, language: &str, tag_name: &str, published_at: &str) -> Self {
+        Self {
+            project: Some(Project::new(
+                project.to_owned(),
+                format!("owner/{}", project),
+                language.to_owned(),
+            )),
+            name: tag_name.to_owned(),
+            tag_name: tag_name.to_owned(),
+            html_url: format!("https://github.com/owner/{}/releases/{}", project, tag_name),
+            body: format!("Release **{}**", tag_name),
+            created_at: published_at.to_owned(),
+            published_at: published_at.to_owned(),
+            stats: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl ReleasesCache {
+    /// Cache already loaded with `releases`
+    pub(crate) fn loaded(releases: Vec<Release>, expired_at: DateTime<Utc>) -> Self {
+        let state = CachedReleases {
+            releases: Arc::new(releases),
+            expired_at,
+        };
+        let cache = Self::default();
+        cache.store_expired_at(&state);
+        *cache.state.try_lock().expect("new cache is not locked") = state;
+        cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn releases() -> Vec<Release> {
+        vec![
+            Release::fake("actix-web", "Rust", "v3.3.2", "2020-12-01T10:00:00Z"),
+            Release::fake("rocket", "Rust", "v0.4.6", "2020-11-20T10:00:00Z"),
+            Release::fake("laravel", "PHP", "v8.10.0", "2020-12-02T10:00:00Z"),
+            Release::fake("symfony", "PHP", "nightly", "2020-11-25T10:00:00Z"),
+            Release::fake("gin", "Go", "v1.6.3", "invalid date"),
+        ]
+    }
+
+    fn names(releases: &[Release]) -> Vec<&str> {
+        releases
+            .iter()
+            .map(|release| release.project.as_ref().unwrap().name.as_str())
+            .collect()
+    }
+
+    fn query(sort: ReleasesSort, order: SortOrder) -> ReleasesQuery {
+        ReleasesQuery {
+            sort: Some(sort),
+            order: Some(order),
+            ..ReleasesQuery::default()
+        }
+    }
+
+    #[test]
+    fn test_apply_filters() {
+        let query = ReleasesQuery {
+            language: Some(" rust ".to_owned()),
+            search: Some("ACTIX".to_owned()),
+            ..ReleasesQuery::default()
+        };
+        assert_eq!(names(&query.apply(&releases())), vec!["actix-web"]);
+
+        // Empty filters are ignored and releases without project are excluded
+        let mut all = releases();
+        all.push(Release::new());
+        let query = ReleasesQuery {
+            language: Some("".to_owned()),
+            search: Some(" ".to_owned()),
+            ..ReleasesQuery::default()
+        };
+        assert_eq!(query.apply(&all).len(), 5);
+    }
+
+    #[test]
+    fn test_apply_sort_published_at() {
+        // Releases without a valid date are last in both orders
+        let releases = releases();
+        assert_eq!(
+            names(&query(ReleasesSort::PublishedAt, SortOrder::Desc).apply(&releases)),
+            vec!["laravel", "actix-web", "symfony", "rocket", "gin"]
+        );
+        assert_eq!(
+            names(&query(ReleasesSort::PublishedAt, SortOrder::Asc).apply(&releases)),
+            vec!["rocket", "symfony", "actix-web", "laravel", "gin"]
+        );
+    }
+
+    #[test]
+    fn test_apply_sort_semver() {
+        // `v8.10.0` is greater than `v3.3.2` and tags without version are last in both orders
+        let releases = releases();
+        assert_eq!(
+            names(&query(ReleasesSort::Semver, SortOrder::Desc).apply(&releases)),
+            vec!["laravel", "actix-web", "gin", "rocket", "symfony"]
+        );
+        assert_eq!(
+            names(&query(ReleasesSort::Semver, SortOrder::Asc).apply(&releases)),
+            vec!["rocket", "gin", "actix-web", "laravel", "symfony"]
+        );
+    }
+
+    #[test]
+    fn test_apply_grouped() {
+        let query = ReleasesQuery {
+            group_by: Some(ReleasesGroup::Language),
+            ..ReleasesQuery::default()
+        };
+        let groups = query.apply_grouped(&releases());
+        assert_eq!(groups.keys().collect::<Vec<_>>(), vec!["Go", "PHP", "Rust"]);
+        assert_eq!(names(&groups["Rust"]), vec!["actix-web", "rocket"]);
+        assert_eq!(names(&groups["PHP"]), vec!["laravel", "symfony"]);
+
+        let groups = ReleasesQuery::default().apply_grouped(&releases());
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[""].len(), 5);
+    }
+
+    #[test]
+    fn test_with_previous() {
+        let projects: Vec<Project> = releases().into_iter().filter_map(|release| release.project).collect();
+        let previous = releases();
+
+        // Rocket and gin fetches failed, laravel has a new release and symfony is no longer fetched
+        let fetched = vec![
+            Release::fake("laravel", "PHP", "v8.11.0", "2020-12-03T10:00:00Z"),
+            Release::fake("actix-web", "Rust", "v3.3.2", "2020-12-01T10:00:00Z"),
+            Release::new(),
+        ];
+        let projects: Vec<Project> = projects.into_iter().filter(|p| p.name != "symfony").collect();
+        let releases = with_previous(&projects, &previous, fetched);
+
+        assert_eq!(names(&releases), vec!["actix-web", "rocket", "laravel", "gin"]);
+        let tags: Vec<&str> = releases.iter().map(|release| release.tag_name.as_str()).collect();
+        assert_eq!(tags, vec!["v3.3.2", "v0.4.6", "v8.11.0", "v1.6.3"]);
+
+        // Nothing to fall back on during the first load
+        assert_eq!(names(&with_previous(&projects, &[], Vec::new())), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_release_events() {
+        let previous = releases();
+
+        // Unchanged tags
+        assert!(release_events(&previous, &releases()).is_empty());
+
+        // New tag
+        let mut current = releases();
+        current[2] = Release::fake("laravel", "PHP", "v8.11.0", "2020-12-03T10:00:00Z");
+        let events = release_events(&previous, &current);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].name, "laravel");
+        assert_eq!(events[0].repo, "owner/laravel");
+        assert_eq!(events[0].tag_name, "v8.11.0");
+        assert_eq!(events[0].previous_tag_name, "v8.10.0");
+        assert_eq!(events[0].published_at, "2020-12-03T10:00:00Z");
+
+        // Rocket recovering from a failed fetch, with and without previous release
+        let projects: Vec<Project> = releases().into_iter().filter_map(|release| release.project).collect();
+        let failed: Vec<Release> = previous.iter().filter(|r| r.tag_name != "v0.4.6").cloned().collect();
+        let kept = with_previous(&projects, &previous, failed.clone());
+        assert!(release_events(&kept, &releases()).is_empty());
+        assert!(release_events(&failed, &releases()).is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_expired_at() {
+        assert_eq!(ReleasesCache::default().expired_at(), None);
+        assert_eq!(ReleasesCache::loaded(Vec::new(), Utc::now()).expired_at(), None);
+
+        let expired_at = Utc.timestamp_millis(1_607_594_400_123);
+        let cache = ReleasesCache::loaded(releases(), expired_at);
+        assert_eq!(cache.expired_at(), Some(expired_at));
+
+        // Available while a refresh holds the lock
+        let _state = cache.state.lock().await;
+        assert_eq!(cache.expired_at(), Some(expired_at));
+    }
+
+    #[test]
+    fn test_cmp_options() {
+        assert_eq!(cmp_options(Some(1), Some(2), SortOrder::Asc), Ordering::Less);
+        assert_eq!(cmp_options(Some(1), Some(2), SortOrder::Desc), Ordering::Greater);
+        for order in [SortOrder::Asc, SortOrder::Desc] {
+            assert_eq!(cmp_options(Some(1), None, order), Ordering::Less);
+            assert_eq!(cmp_options(None, Some(1), order), Ordering::Greater);
+            assert_eq!(cmp_options::<i32>(None, None, order), Ordering::Equal);
+        }
     }
 }
@@ -0,0 +1,215 @@
+//! Repository statistics model module
+
+use crate::db;
+use crate::db::schema::repository_stats;
+use crate::db::MysqlPool;
+use crate::metrics::Metrics;
+use crate::models::release::Release;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use color_eyre::Result;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Number of days used to compute statistics trends
+const TREND_DAYS: i64 = 7;
+
+/// Repository statistics from Github API
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RepositoryStats {
+    #[serde(rename(deserialize = "stargazers_count"))]
+    pub stars: i32,
+    #[serde(rename(deserialize = "open_issues_count"))]
+    pub open_issues: i32,
+    pub pushed_at: Option<String>,
+    pub license: Option<License>,
+    pub archived: bool,
+    /// Stars gained since last week, if a snapshot is old enough
+    #[serde(default)]
+    pub stars_gained: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct License {
+    pub name: String,
+    pub spdx_id: Option<String>,
+}
+
+/// Repository statistics stored in database at each releases cache refresh
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "repository_stats"]
+pub struct RepositoryStatsSnapshot {
+    pub id: String,
+    pub repo: String,
+    pub stars: i32,
+    pub open_issues: i32,
+    pub pushed_at: Option<NaiveDateTime>,
+    pub license: Option<String>,
+    pub archived: bool,
+    pub created_at: NaiveDateTime,
+}
+
+impl RepositoryStats {
+    /// Returns the license short name (SPDX identifier if available)
+    pub fn license_name(&self) -> &str {
+        match &self.license {
+            Some(License {
+                spdx_id: Some(spdx_id), ..
+            }) if spdx_id != "NOASSERTION" => spdx_id,
+            Some(license) => &license.name,
+            None => "",
+        }
+    }
+
+    /// Returns stars gained since last week formatted with a sign (`+12`, `-3`), or an empty string
+    pub fn stars_trend(&self) -> String {
+        self.stars_gained
+            .map_or_else(String::new, |gained| format!("{:+}", gained))
+    }
+
+    /// Returns the CSS class of the stars trend
+    pub fn stars_trend_class(&self) -> &'static str {
+        match self.stars_gained {
+            Some(gained) if gained > 0 => "text-success",
+            Some(gained) if gained < 0 => "text-danger",
+            _ => "text-muted",
+        }
+    }
+
+    /// Stores a snapshot of each release repository statistics and computes stars gained since last week.
+    /// Database errors are logged and releases are returned without trends.
+    pub async fn track(pool: MysqlPool, metrics: &Metrics, mut releases: Vec<Release>) -> Vec<Release> {
+        let stats: Vec<(String, RepositoryStats)> = releases
+            .iter()
+            .filter_map(|release| match (&release.project, &release.stats) {
+                (Some(project), Some(stats)) => Some((project.repo.clone(), stats.clone())),
+                _ => None,
+            })
+            .collect();
+
+        let trends = db::block(metrics, "repository_stats.save_all", move || {
+            let connection = pool.get().map_err(|e| e.to_string())?;
+            RepositoryStatsSnapshot::save_all(&connection, stats).map_err(|e| e.to_string())
+        })
+        .await;
+
+        match trends {
+            Ok(trends) => {
+                for release in releases.iter_mut() {
+                    if let (Some(project), Some(stats)) = (&release.project, &mut release.stats) {
+                        stats.stars_gained = trends.get(&project.repo).copied();
+                    }
+                }
+            }
+            Err(e) => error!("Repository statistics: {}", e),
+        }
+        releases
+    }
+}
+
+impl RepositoryStatsSnapshot {
+    /// Creates a snapshot of repository statistics
+    pub fn create(
+        connection: &MysqlConnection,
+        repo: String,
+        stats: &RepositoryStats,
+    ) -> Result<Self, diesel::result::Error> {
+        let snapshot = Self {
+            id: Uuid::new_v4().to_string(),
+            repo,
+            stars: stats.stars,
+            open_issues: stats.open_issues,
+            pushed_at: stats
+                .pushed_at
+                .as_ref()
+                .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+                .map(|date| date.naive_utc()),
+            license: stats.license.as_ref().map(|license| license.name.clone()),
+            archived: stats.archived,
+            created_at: Utc::now().naive_utc(),
+        };
+
+        diesel::insert_into(repository_stats::table)
+            .values(&snapshot)
+            .execute(connection)?;
+
+        Ok(snapshot)
+    }
+
+    /// Get the most recent snapshot of a repository created before a date
+    pub fn get_before(
+        connection: &MysqlConnection,
+        repository: &str,
+        date: NaiveDateTime,
+    ) -> Result<Option<Self>, diesel::result::Error> {
+        use crate::db::schema::repository_stats::dsl::*;
+
+        repository_stats
+            .filter(repo.eq(repository))
+            .filter(created_at.le(date))
+            .order(created_at.desc())
+            .first::<Self>(connection)
+            .optional()
+    }
+
+    /// Deletes the snapshots of a repository created before a date
+    pub fn delete_before(
+        connection: &MysqlConnection,
+        repository: &str,
+        date: NaiveDateTime,
+    ) -> Result<usize, diesel::result::Error> {
+        use crate::db::schema::repository_stats::dsl::*;
+
+        diesel::delete(repository_stats.filter(repo.eq(repository)).filter(created_at.lt(date))).execute(connection)
+    }
+
+    /// Saves a snapshot for each repository and returns stars gained since last week by repository.
+    ///
+    /// Snapshots older than the one used to compute the trend are deleted.
+    pub fn save_all(
+        connection: &MysqlConnection,
+        stats: Vec<(String, RepositoryStats)>,
+    ) -> Result<HashMap<String, i32>, diesel::result::Error> {
+        let last_week = (Utc::now() - Duration::days(TREND_DAYS)).naive_utc();
+
+        connection.transaction(|| {
+            let mut trends = HashMap::new();
+            for (repo, stats) in stats {
+                if let Some(previous) = Self::get_before(connection, &repo, last_week)? {
+                    trends.insert(repo.clone(), stats.stars - previous.stars);
+                    Self::delete_before(connection, &repo, previous.created_at)?;
+                }
+                Self::create(connection, repo, &stats)?;
+            }
+            Ok(trends)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(stars_gained: Option<i32>) -> RepositoryStats {
+        RepositoryStats {
+            stars: 100,
+            open_issues: 0,
+            pushed_at: None,
+            license: None,
+            archived: false,
+            stars_gained,
+        }
+    }
+
+    #[test]
+    fn test_stars_trend() {
+        assert_eq!(stats(Some(12)).stars_trend(), "+12");
+        assert_eq!(stats(Some(12)).stars_trend_class(), "text-success");
+        assert_eq!(stats(Some(-3)).stars_trend(), "-3");
+        assert_eq!(stats(Some(-3)).stars_trend_class(), "text-danger");
+        assert_eq!(stats(Some(0)).stars_trend(), "+0");
+        assert_eq!(stats(Some(0)).stars_trend_class(), "text-muted");
+        assert_eq!(stats(None).stars_trend(), "");
+    }
+}
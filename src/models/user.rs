@@ -108,4 +108,20 @@ impl UserList {
 
         Ok(UserList(result))
     }
+
+    /// Chunk of users ordered by ID, starting after the `after` ID (keyset pagination)
+    pub fn list_after(
+        connection: &MysqlConnection,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Self, diesel::result::Error> {
+        use crate::db::schema::users::dsl::*;
+
+        let mut query = users.order(id.asc()).limit(limit).into_boxed();
+        if let Some(after) = after {
+            query = query.filter(id.gt(after));
+        }
+
+        Ok(UserList(query.load::<User>(connection)?))
+    }
 }
@@ -0,0 +1,75 @@
+//! Content negotiation module
+
+use actix_web::{http::header, HttpRequest};
+
+/// Chooses the media type with the highest quality in the `Accept` header, ties going to the first one.
+///
+/// `media_types` maps the supported media types to their value. Other media types, including wildcards,
+/// and media types refused with `q=0` are ignored. Returns `None` if no supported media type is accepted.
+pub fn negotiate<T: Copy>(req: &HttpRequest, media_types: &[(&str, T)]) -> Option<T> {
+    let accept = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .unwrap_or_default();
+
+    let mut best: Option<(T, f32)> = None;
+    for media_type in accept.split(',') {
+        let mut params = media_type.split(';');
+        let value = match params.next().map(str::trim).and_then(|media_type| {
+            media_types
+                .iter()
+                .find(|(supported, _)| supported.eq_ignore_ascii_case(media_type))
+        }) {
+            Some((_, value)) => *value,
+            None => continue,
+        };
+        let q = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        match best {
+            Some((_, best_q)) if q <= best_q => (),
+            _ if q > 0.0 => best = Some((value, q)),
+            _ => (),
+        }
+    }
+
+    best.map(|(value, _)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    const MEDIA_TYPES: &[(&str, &str)] = &[("application/json", "json"), ("text/csv", "csv"), ("text/html", "html")];
+
+    fn negotiate_accept(accept: &str) -> Option<&'static str> {
+        let req = TestRequest::default().header(header::ACCEPT, accept).to_http_request();
+        negotiate(&req, MEDIA_TYPES)
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate(&TestRequest::default().to_http_request(), MEDIA_TYPES), None);
+        assert_eq!(negotiate_accept(""), None);
+        assert_eq!(negotiate_accept("*/*"), None);
+        assert_eq!(negotiate_accept("image/png, Text/CSV"), Some("csv"));
+
+        // Ties going to the first media type
+        assert_eq!(negotiate_accept("text/csv, application/json"), Some("csv"));
+        assert_eq!(negotiate_accept("text/html;q=0.8, text/csv;q=0.8"), Some("html"));
+
+        // Qualities
+        assert_eq!(negotiate_accept("application/json;q=0.5, text/csv"), Some("csv"));
+        assert_eq!(
+            negotiate_accept("text/csv;q=0.2, text/html; charset=utf-8; q=0.9"),
+            Some("html")
+        );
+        assert_eq!(negotiate_accept("text/csv;q=invalid, text/html;q=0.9"), Some("csv"));
+        assert_eq!(negotiate_accept("text/csv;q=0, */*"), None);
+        assert_eq!(negotiate_accept("text/csv;q=0, text/html;q=0.1"), Some("html"));
+    }
+}
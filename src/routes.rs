@@ -1,7 +1,7 @@
 //! List all server routes
 
 use crate::handlers;
-use crate::handlers::{releases, users};
+use crate::handlers::{admin, drift, events, health, releases, users};
 use crate::middlewares;
 use actix_files as fs;
 use actix_web::{guard, web};
@@ -16,6 +16,7 @@ pub fn api(cfg: &mut web::ServiceConfig) {
                 web::scope("/users")
                     .wrap(middlewares::auth::Authentication)
                     .route("", web::get().to(users::get_users))
+                    .route("/export", web::get().to(users::export))
                     .route("/{id}", web::get().to(users::get_by_id))
                     .route("/{id}", web::put().to(users::update))
                     .route("/{id}", web::delete().to(users::delete)),
@@ -23,14 +24,50 @@ pub fn api(cfg: &mut web::ServiceConfig) {
     );
 }
 
+/// Defines operational routes, restricted by `guard`
+pub fn ops(cfg: &mut web::ServiceConfig, guard: middlewares::admin::AdminGuard) {
+    cfg.service(
+        web::resource("/metrics")
+            .wrap(guard.clone())
+            .route(web::get().to(handlers::metrics)),
+    )
+    .service(
+        web::resource("/health/live")
+            .wrap(guard.clone())
+            .route(web::get().to(health::live)),
+    )
+    .service(
+        web::resource("/health/ready")
+            .wrap(guard.clone())
+            .route(web::get().to(health::ready)),
+    )
+    .service(
+        web::resource("/health_check")
+            .wrap(guard.clone())
+            .route(web::get().to(handlers::health_check)),
+    )
+    .service(
+        web::resource("/admin/log-levels")
+            .wrap(guard)
+            .route(web::get().to(admin::log_levels))
+            .route(web::put().to(admin::set_log_levels)),
+    );
+}
+
 /// Defines web's routes
 pub fn web(cfg: &mut web::ServiceConfig) {
     cfg.route("/", web::get().to(handlers::index))
-        .route("/health_check", web::get().to(handlers::health_check))
+        .route("/events", web::get().to(events::stream))
         .route("/ws", web::get().to(handlers::ws::index))
+        .route("/ws/releases", web::get().to(handlers::ws::releases))
         .route("/github/{user}/{repo}", web::get().to(releases::github))
         .route("/github/async", web::get().to(releases::github_async))
+        .route("/github/feed.atom", web::get().to(releases::github_feed_atom))
+        .route("/github/feed.rss", web::get().to(releases::github_feed_rss))
         .route("/github-page", web::get().to(releases::github_page))
+        .route("/github/drift", web::post().to(drift::report))
+        .route("/github-drift-page", web::get().to(drift::page))
+        .route("/github-drift-page", web::post().to(drift::page_report))
         .service(handlers::big_json_stream)
         .service(handlers::internal_error)
         .service(handlers::not_found)
@@ -0,0 +1,292 @@
+//! Server-Sent Events module
+//!
+//! The broadcaster is a system service sending application events to all `text/event-stream` clients.
+//! The last events are kept in memory so that a reconnecting client can resume from its `Last-Event-ID`.
+//! Event identifiers are prefixed with the broadcaster start time, as the sequence restarts with the server.
+
+use crate::ws::protocol::ReleaseEvent;
+use actix::{Actor, AsyncContext, Context, Handler, Message, MessageResult, Supervised, SystemService};
+use actix_web::{web::Bytes, Error};
+use chrono::Utc;
+use futures::channel::mpsc;
+use futures::StreamExt;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+
+/// Number of events kept for `Last-Event-ID` resumption
+const REPLAY_BUFFER_SIZE: usize = 100;
+
+/// Number of pending events per client before it is considered too slow and disconnected
+const CLIENT_BUFFER_SIZE: usize = REPLAY_BUFFER_SIZE + 32;
+
+/// Interval between two keep-alive comments
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Reconnection delay advised to clients (in milliseconds)
+const RETRY_DELAY: u64 = 5000;
+
+/// Event identifier, `<epoch>-<sequence>`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventId {
+    /// Broadcaster start time (in milliseconds)
+    epoch: i64,
+    sequence: u64,
+}
+
+impl fmt::Display for EventId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.epoch, self.sequence)
+    }
+}
+
+impl FromStr for EventId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (epoch, sequence) = s.split_once('-').ok_or_else(|| format!("invalid event ID: {}", s))?;
+        match (epoch.parse(), sequence.parse()) {
+            (Ok(epoch), Ok(sequence)) => Ok(Self { epoch, sequence }),
+            _ => Err(format!("invalid event ID: {}", s)),
+        }
+    }
+}
+
+/// Application events
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Event {
+    ReleaseChanged { release: ReleaseEvent },
+    UserCreated { id: String },
+    UserDeleted { id: String },
+}
+
+impl Event {
+    /// Event name sent in the `event` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ReleaseChanged { .. } => "release_changed",
+            Self::UserCreated { .. } => "user_created",
+            Self::UserDeleted { .. } => "user_deleted",
+        }
+    }
+}
+
+/// Publishes an event to all clients
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Publish {
+    pub event: Event,
+}
+
+/// Registers a new client and returns its events stream.
+/// Buffered events following `last_event_id` are sent first.
+#[derive(Message)]
+#[rtype(result = "EventStream")]
+pub struct Subscribe {
+    pub last_event_id: Option<EventId>,
+}
+
+/// Stream of encoded events sent to a client
+pub struct EventStream {
+    receiver: mpsc::Receiver<Bytes>,
+}
+
+impl futures::Stream for EventStream {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().receiver.poll_next_unpin(cx).map(|bytes| bytes.map(Ok))
+    }
+}
+
+pub struct Broadcaster {
+    epoch: i64,
+    /// Sequence of the last published event
+    sequence: u64,
+    events: VecDeque<(u64, Bytes)>,
+    clients: Vec<mpsc::Sender<Bytes>>,
+}
+
+impl Default for Broadcaster {
+    fn default() -> Self {
+        Self {
+            epoch: Utc::now().timestamp_millis(),
+            sequence: 0,
+            events: VecDeque::new(),
+            clients: Vec::new(),
+        }
+    }
+}
+
+impl Broadcaster {
+    /// Sends data to all clients, disconnected and slow clients being removed
+    fn send_all(&mut self, data: &Bytes) {
+        self.clients.retain_mut(|client| client.try_send(data.clone()).is_ok());
+    }
+
+    /// Returns buffered events to send to a client resuming after `last_event_id`.
+    /// All buffered events are replayed if the identifier is unknown (server restarted for example)
+    /// or no longer buffered, the events evicted in the meantime being lost.
+    fn replay(&self, last_event_id: Option<EventId>) -> impl Iterator<Item = &Bytes> {
+        let from = match last_event_id {
+            Some(id) if id.epoch == self.epoch && id.sequence <= self.sequence => id.sequence + 1,
+            Some(_) => 0,
+            None => self.sequence + 1,
+        };
+        self.events
+            .iter()
+            .filter(move |(sequence, _)| *sequence >= from)
+            .map(|(_, data)| data)
+    }
+}
+
+/// Encodes an event in the `text/event-stream` format
+fn encode(id: EventId, event: &Event) -> Result<Bytes, serde_json::Error> {
+    let data = serde_json::to_string(event)?;
+    Ok(Bytes::from(format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        id,
+        event.name(),
+        data
+    )))
+}
+
+impl Actor for Broadcaster {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(KEEP_ALIVE_INTERVAL, |act, _| {
+            act.send_all(&Bytes::from_static(b": keep-alive\n\n"));
+        });
+    }
+}
+
+impl Supervised for Broadcaster {}
+
+impl SystemService for Broadcaster {}
+
+impl Handler<Publish> for Broadcaster {
+    type Result = ();
+
+    fn handle(&mut self, msg: Publish, _: &mut Context<Self>) {
+        self.sequence += 1;
+        let id = EventId {
+            epoch: self.epoch,
+            sequence: self.sequence,
+        };
+        let data = match encode(id, &msg.event) {
+            Ok(data) => data,
+            Err(e) => return error!("SSE: failed to encode event {:?}: {}", msg.event, e),
+        };
+
+        self.send_all(&data);
+
+        if self.events.len() == REPLAY_BUFFER_SIZE {
+            self.events.pop_front();
+        }
+        self.events.push_back((self.sequence, data));
+    }
+}
+
+impl Handler<Subscribe> for Broadcaster {
+    type Result = MessageResult<Subscribe>;
+
+    fn handle(&mut self, msg: Subscribe, _: &mut Context<Self>) -> Self::Result {
+        let (mut sender, receiver) = mpsc::channel(CLIENT_BUFFER_SIZE);
+
+        // The channel is empty and large enough to hold the whole replay buffer
+        let _ = sender.try_send(Bytes::from(format!("retry: {}\n\n", RETRY_DELAY)));
+        for data in self.replay(msg.last_event_id) {
+            let _ = sender.try_send(data.clone());
+        }
+
+        self.clients.push(sender);
+        MessageResult(EventStream { receiver })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_created(id: &str) -> Publish {
+        Publish {
+            event: Event::UserCreated { id: id.to_owned() },
+        }
+    }
+
+    /// Returns the chunks already sent to a subscriber, skipping the retry preamble
+    async fn subscribe(broadcaster: &actix::Addr<Broadcaster>, last_event_id: Option<EventId>) -> Vec<String> {
+        let mut stream = broadcaster.send(Subscribe { last_event_id }).await.unwrap();
+        stream.receiver.close();
+
+        let mut chunks = Vec::new();
+        while let Some(Ok(chunk)) = stream.next().await {
+            chunks.push(String::from_utf8(chunk.to_vec()).unwrap());
+        }
+        assert_eq!(chunks.remove(0), "retry: 5000\n\n");
+        chunks
+    }
+
+    #[test]
+    fn test_event_id() {
+        let id: EventId = "1607594400000-12".parse().unwrap();
+        assert_eq!(
+            id,
+            EventId {
+                epoch: 1607594400000,
+                sequence: 12
+            }
+        );
+        assert_eq!(id.to_string(), "1607594400000-12");
+
+        assert!("12".parse::<EventId>().is_err());
+        assert!("abc-12".parse::<EventId>().is_err());
+        assert!("1607594400000-".parse::<EventId>().is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_replay() {
+        let broadcaster = Broadcaster::default();
+        let epoch = broadcaster.epoch;
+        let broadcaster = broadcaster.start();
+        for i in 1..=REPLAY_BUFFER_SIZE + 15 {
+            broadcaster.send(user_created(&i.to_string())).await.unwrap();
+        }
+        let id = |sequence| Some(EventId { epoch, sequence });
+
+        // New client
+        assert!(subscribe(&broadcaster, None).await.is_empty());
+
+        // Events following the last one received
+        let chunks = subscribe(&broadcaster, id(112)).await;
+        assert_eq!(
+            chunks,
+            vec![
+                format!("id: {}-113\nevent: user_created\ndata: {{\"id\":\"113\"}}\n\n", epoch),
+                format!("id: {}-114\nevent: user_created\ndata: {{\"id\":\"114\"}}\n\n", epoch),
+                format!("id: {}-115\nevent: user_created\ndata: {{\"id\":\"115\"}}\n\n", epoch),
+            ]
+        );
+        assert!(subscribe(&broadcaster, id(115)).await.is_empty());
+
+        // Evicted, future and previous run identifiers: the whole buffer is replayed
+        for last_event_id in [
+            id(12),
+            id(200),
+            Some(EventId {
+                epoch: 1,
+                sequence: 112,
+            }),
+        ] {
+            let chunks = subscribe(&broadcaster, last_event_id).await;
+            assert_eq!(chunks.len(), REPLAY_BUFFER_SIZE);
+            assert!(chunks[0].starts_with(&format!("id: {}-16\n", epoch)));
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Streaming responses module
+//!
+//! [`StreamResponse`] serializes a stream of items as a JSON array, NDJSON or CSV
+//! depending on the `Accept` request header. Items are batched into chunks of about [`CHUNK_SIZE`] bytes.
+
+use crate::errors::AppError;
+use crate::negotiation::negotiate;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpRequest, HttpResponse, Responder};
+use futures::future::{ready, Ready};
+use futures::stream::{LocalBoxStream, Stream, StreamExt};
+use serde::Serialize;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Size above which a chunk is sent (in bytes)
+pub const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Streaming response formats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamFormat {
+    /// JSON array (`application/json`)
+    Json,
+    /// One JSON document per line (`application/x-ndjson`)
+    NdJson,
+    /// CSV with a header line (`text/csv`)
+    Csv,
+}
+
+impl StreamFormat {
+    /// Chooses the preferred format of the `Accept` header
+    pub fn from_request(req: &HttpRequest, default: Self) -> Self {
+        negotiate(
+            req,
+            &[
+                ("application/json", Self::Json),
+                ("application/x-ndjson", Self::NdJson),
+                ("application/ndjson", Self::NdJson),
+                ("text/csv", Self::Csv),
+            ],
+        )
+        .unwrap_or(default)
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::NdJson => "application/x-ndjson",
+            Self::Csv => "text/csv; charset=utf-8",
+        }
+    }
+}
+
+/// Streaming responder over a stream of serializable items
+pub struct StreamResponse<T> {
+    stream: LocalBoxStream<'static, Result<T, Error>>,
+    /// Format used if `Accept` header does not match any format
+    default_format: StreamFormat,
+}
+
+impl<T: Serialize + 'static> StreamResponse<T> {
+    /// Creates a responder from a stream of items
+    pub fn new<S>(stream: S) -> Self
+    where
+        S: Stream<Item = T> + 'static,
+    {
+        Self {
+            stream: stream.map(Ok).boxed_local(),
+            default_format: StreamFormat::Json,
+        }
+    }
+
+    /// Creates a responder from a stream of results, the response is aborted on the first error
+    pub fn try_new<S, E>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<T, E>> + 'static,
+        E: Into<Error> + 'static,
+    {
+        Self {
+            stream: stream.map(|item| item.map_err(Into::into)).boxed_local(),
+            default_format: StreamFormat::Json,
+        }
+    }
+
+    /// Sets the format used if `Accept` header does not match any format (JSON by default)
+    pub fn default_format(mut self, format: StreamFormat) -> Self {
+        self.default_format = format;
+        self
+    }
+
+    /// Serializes the stream in the given format
+    pub fn into_body(self, format: StreamFormat) -> impl Stream<Item = Result<Bytes, Error>> {
+        Encoder {
+            stream: self.stream,
+            format,
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            count: 0,
+            done: false,
+        }
+    }
+}
+
+impl<T: Serialize + 'static> Responder for StreamResponse<T> {
+    type Error = Error;
+    type Future = Ready<Result<HttpResponse, Error>>;
+
+    fn respond_to(self, req: &HttpRequest) -> Self::Future {
+        let format = StreamFormat::from_request(req, self.default_format);
+        ready(Ok(HttpResponse::Ok()
+            .content_type(format.content_type())
+            .streaming(self.into_body(format))))
+    }
+}
+
+/// Stream serializing items into chunks
+struct Encoder<T> {
+    stream: LocalBoxStream<'static, Result<T, Error>>,
+    format: StreamFormat,
+    buf: Vec<u8>,
+    /// Number of items already serialized
+    count: usize,
+    done: bool,
+}
+
+impl<T: Serialize> Encoder<T> {
+    /// Serializes an item into the buffer
+    fn push(&mut self, item: &T) -> Result<(), Error> {
+        match self.format {
+            StreamFormat::Json => {
+                self.buf.push(if self.count == 0 { b'[' } else { b',' });
+                serde_json::to_writer(&mut self.buf, item)?;
+            }
+            StreamFormat::NdJson => {
+                serde_json::to_writer(&mut self.buf, item)?;
+                self.buf.push(b'\n');
+            }
+            StreamFormat::Csv => {
+                // The header line is written with the first item
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(self.count == 0)
+                    .from_writer(&mut self.buf);
+                writer.serialize(item).map_err(ErrorInternalServerError)?;
+                writer.flush()?;
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Terminates the output, an empty stream being serialized as an empty JSON array
+    fn finish(&mut self) {
+        if self.format == StreamFormat::Json {
+            if self.count == 0 {
+                self.buf.push(b'[');
+            }
+            self.buf.push(b']');
+        }
+    }
+
+    /// Takes the buffered chunk
+    fn chunk(&mut self) -> Bytes {
+        Bytes::from(std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE)))
+    }
+}
+
+impl<T: Serialize> Stream for Encoder<T> {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        while !this.done {
+            match this.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(item))) => {
+                    if let Err(e) = this.push(&item) {
+                        this.done = true;
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                    if this.buf.len() >= CHUNK_SIZE {
+                        return Poll::Ready(Some(Ok(this.chunk())));
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    // The response is already started, errors do not go through `error_response`
+                    match e.as_error::<AppError>() {
+                        Some(error) => error.log(),
+                        None => error!("Response stream aborted: {}", e),
+                    }
+                    this.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                Poll::Ready(None) => {
+                    this.done = true;
+                    this.finish();
+                }
+                // Sends what is available while waiting for the next items
+                Poll::Pending if !this.buf.is_empty() => return Poll::Ready(Some(Ok(this.chunk()))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        if this.buf.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Ready(Some(Ok(this.chunk())))
+        }
+    }
+}
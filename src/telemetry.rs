@@ -0,0 +1,200 @@
+//! OpenTelemetry tracing module
+//!
+//! Spans are created with `tracing` and exported by the `tracing-opentelemetry` layer.
+//! The W3C `traceparent`/`tracestate` headers are extracted from incoming requests and
+//! injected into outbound requests.
+
+use color_eyre::Result;
+use opentelemetry::global;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::{trace, Resource};
+use opentelemetry::KeyValue;
+use std::str::FromStr;
+use tracing_subscriber::prelude::*;
+
+/// Spans exporter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exporter {
+    /// Tracing is disabled
+    None,
+    /// Spans are printed to stdout, for local use
+    Stdout,
+    /// Spans are sent to an OpenTelemetry collector with OTLP (gRPC)
+    Otlp,
+}
+
+impl FromStr for Exporter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "stdout" => Ok(Self::Stdout),
+            "otlp" => Ok(Self::Otlp),
+            _ => Err(format!("invalid tracing exporter: {}", s)),
+        }
+    }
+}
+
+/// Flushes remaining spans when dropped
+pub struct Guard {
+    /// Tokio 1 runtime used by the OTLP exporter (Actix runs on Tokio 0.2)
+    runtime: Option<tokio::runtime::Runtime>,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        global::shutdown_tracer_provider();
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Initializes the tracer provider and installs the `tracing` subscriber
+pub fn init(exporter: Exporter, endpoint: &str, service_name: &str) -> Result<Guard> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let config = trace::config().with_resource(Resource::new(vec![KeyValue::new(
+        "service.name",
+        service_name.to_owned(),
+    )]));
+
+    let (tracer, runtime) = match exporter {
+        Exporter::None => return Ok(Guard { runtime: None }),
+        Exporter::Stdout => (
+            opentelemetry::sdk::export::trace::stdout::new_pipeline()
+                .with_trace_config(config)
+                .install_simple(),
+            None,
+        ),
+        Exporter::Otlp => {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .thread_name("otlp-exporter")
+                .enable_all()
+                .build()?;
+            // The batch span processor is spawned on the current runtime
+            let _guard = runtime.enter();
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .with_endpoint(endpoint)
+                .with_trace_config(config)
+                .with_tonic()
+                .install_batch(opentelemetry::runtime::Tokio)?;
+            (tracer, Some(runtime))
+        }
+    };
+
+    let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    info!("Tracing configuration OK ({:?} exporter)", exporter);
+    Ok(Guard { runtime })
+}
+
+/// Reads trace context headers of an incoming request
+pub struct HeaderExtractor<'a>(pub &'a actix_web::http::HeaderMap);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Writes trace context headers of an outbound request
+pub struct HeaderInjector<'a>(pub &'a mut reqwest::header::HeaderMap);
+
+impl<'a> Injector for HeaderInjector<'a> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            reqwest::header::HeaderName::from_bytes(key.as_bytes()),
+            reqwest::header::HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{HeaderMap, HeaderName, HeaderValue};
+    use opentelemetry::propagation::TextMapPropagator;
+    use opentelemetry::trace::{Span, TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry::Context;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    fn incoming_headers(traceparent: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("traceparent"),
+            HeaderValue::from_static(traceparent),
+        );
+        headers.insert(
+            HeaderName::from_static("tracestate"),
+            HeaderValue::from_static("vendor=value"),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_exporter_from_str() {
+        assert_eq!("none".parse(), Ok(Exporter::None));
+        assert_eq!("stdout".parse(), Ok(Exporter::Stdout));
+        assert_eq!("otlp".parse(), Ok(Exporter::Otlp));
+        assert!("OTLP".parse::<Exporter>().is_err());
+        assert!("jaeger".parse::<Exporter>().is_err());
+        assert!("".parse::<Exporter>().is_err());
+    }
+
+    #[test]
+    fn test_extract_traceparent() {
+        let propagator = TraceContextPropagator::new();
+
+        let headers = incoming_headers("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01");
+        let cx = propagator.extract(&HeaderExtractor(&headers));
+        let span_context = cx.remote_span_context().unwrap();
+        assert_eq!(span_context.trace_id().to_hex(), TRACE_ID);
+        assert_eq!(span_context.span_id().to_hex(), "00f067aa0ba902b7");
+        assert!(span_context.is_sampled());
+        assert_eq!(span_context.trace_state().header(), "vendor=value");
+
+        let headers = incoming_headers("00-invalid-00f067aa0ba902b7-01");
+        let cx = propagator.extract(&HeaderExtractor(&headers));
+        assert!(cx.remote_span_context().is_none());
+    }
+
+    #[test]
+    fn test_inject_traceparent() {
+        let propagator = TraceContextPropagator::new();
+        let provider = trace::TracerProvider::builder().build();
+        let tracer = provider.get_tracer("test", None);
+
+        // Outbound request made within a child span of the incoming request
+        let headers = incoming_headers("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01");
+        let parent = propagator.extract(&HeaderExtractor(&headers));
+        let span = tracer.start_with_context("GET api.github.com", parent);
+        let span_id = span.span_context().span_id().to_hex();
+        let cx = Context::new().with_span(span);
+
+        let mut outbound = reqwest::header::HeaderMap::new();
+        propagator.inject_context(&cx, &mut HeaderInjector(&mut outbound));
+        assert_eq!(
+            outbound.get("traceparent").unwrap(),
+            &format!("00-{}-{}-01", TRACE_ID, span_id)
+        );
+        assert_ne!(span_id, "00f067aa0ba902b7");
+        assert_eq!(outbound.get("tracestate").unwrap(), "vendor=value");
+
+        // Nothing is injected without trace context
+        let mut outbound = reqwest::header::HeaderMap::new();
+        propagator.inject_context(&Context::new(), &mut HeaderInjector(&mut outbound));
+        assert!(outbound.is_empty());
+    }
+}
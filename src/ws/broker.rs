@@ -0,0 +1,336 @@
+//! WebSockets broker module
+//!
+//! The broker is a system service tracking all WebSocket sessions and their rooms.
+//! Messages answering a client request carry its correlation ID (`request_id`).
+
+use crate::ws::limits::QueuePolicy;
+use crate::ws::protocol::{Envelope, Member, ReleaseEvent, RequestId, ServerMessage, SessionId};
+use actix::prelude::SendError;
+use actix::{Actor, Context, Handler, Message, Recipient, Supervised, SystemService};
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap};
+
+/// Read-only room receiving releases changes
+pub const RELEASES_ROOM: &str = "releases";
+
+/// Registers a new session of an authenticated user and returns its identifier
+#[derive(Message)]
+#[rtype(result = "SessionId")]
+pub struct Connect {
+    pub addr: Recipient<Envelope<ServerMessage>>,
+    /// Notified when the session outbound queue is full with the `Disconnect` policy
+    pub overloaded: Recipient<Overloaded>,
+    pub policy: QueuePolicy,
+    pub user_id: String,
+}
+
+/// Sent to a session that must be closed because its outbound queue is full
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Overloaded;
+
+/// Unregisters a session and removes it from all its rooms
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Disconnect {
+    pub id: SessionId,
+}
+
+/// Adds a session to a room (the room is created if needed)
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Join {
+    pub id: SessionId,
+    pub request_id: Option<RequestId>,
+    pub room: String,
+}
+
+/// Removes a session from a room (the room is deleted if empty)
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Leave {
+    pub id: SessionId,
+    pub request_id: Option<RequestId>,
+    pub room: String,
+}
+
+/// Sends a text message to all members of a room
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Broadcast {
+    pub id: SessionId,
+    pub request_id: Option<RequestId>,
+    pub room: String,
+    pub text: String,
+}
+
+/// Sends the list of room members to a session
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ListMembers {
+    pub id: SessionId,
+    pub request_id: Option<RequestId>,
+    pub room: String,
+}
+
+struct Session {
+    addr: Recipient<Envelope<ServerMessage>>,
+    overloaded: Recipient<Overloaded>,
+    policy: QueuePolicy,
+    /// Set once the session has been asked to disconnect, no more messages are sent to it
+    disconnecting: Cell<bool>,
+    user_id: String,
+}
+
+/// Sends releases changes to members of the releases room
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct PublishReleases {
+    pub events: Vec<ReleaseEvent>,
+}
+
+#[derive(Default)]
+pub struct Broker {
+    next_id: SessionId,
+    sessions: HashMap<SessionId, Session>,
+    rooms: HashMap<String, BTreeSet<SessionId>>,
+}
+
+impl Broker {
+    /// Returns the user ID of a session
+    fn user_id(&self, id: SessionId) -> String {
+        self.sessions
+            .get(&id)
+            .map(|session| session.user_id.clone())
+            .unwrap_or_default()
+    }
+
+    /// Sends a message to a session
+    fn send(&self, id: SessionId, request_id: Option<RequestId>, message: ServerMessage) {
+        let session = match self.sessions.get(&id) {
+            Some(session) if !session.disconnecting.get() => session,
+            _ => return,
+        };
+
+        match session.addr.try_send(Envelope::new(request_id, message)) {
+            Ok(()) => (),
+            Err(SendError::Full(_)) if session.policy == QueuePolicy::Drop => {
+                warn!("WS broker: outbound queue of session {} is full, message dropped", id);
+            }
+            Err(SendError::Full(_)) => {
+                session.disconnecting.set(true);
+                let _ = session.overloaded.do_send(Overloaded);
+            }
+            Err(e) => warn!("WS broker: failed to send message to session {}: {}", id, e),
+        }
+    }
+
+    /// Sends a message to all members of a room.
+    /// The copy sent to the requesting session (if any) carries the request correlation ID.
+    fn send_room(&self, room: &str, message: ServerMessage, requester: Option<(SessionId, Option<RequestId>)>) {
+        if let Some(members) = self.rooms.get(room) {
+            for id in members {
+                let request_id = match requester {
+                    Some((requester, request_id)) if requester == *id => request_id,
+                    _ => None,
+                };
+                self.send(*id, request_id, message.clone());
+            }
+        }
+    }
+
+    /// Removes a session from a room and notifies remaining members
+    fn leave(&mut self, id: SessionId, room: &str) -> bool {
+        let mut removed = false;
+        if let Some(members) = self.rooms.get_mut(room) {
+            removed = members.remove(&id);
+            if members.is_empty() {
+                self.rooms.remove(room);
+            }
+        }
+
+        if removed {
+            self.send_room(
+                room,
+                ServerMessage::Left {
+                    room: room.to_owned(),
+                    session_id: id,
+                    user_id: self.user_id(id),
+                },
+                None,
+            );
+        }
+        removed
+    }
+
+    /// Returns `true` if the session is a member of the room
+    fn is_member(&self, id: SessionId, room: &str) -> bool {
+        match self.rooms.get(room) {
+            Some(members) => members.contains(&id),
+            None => false,
+        }
+    }
+}
+
+impl Actor for Broker {
+    type Context = Context<Self>;
+}
+
+impl Supervised for Broker {}
+
+impl SystemService for Broker {}
+
+impl Handler<Connect> for Broker {
+    type Result = SessionId;
+
+    fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> Self::Result {
+        self.next_id += 1;
+        let id = self.next_id;
+        debug!("WS broker: session {} of user {} connected", id, msg.user_id);
+        self.sessions.insert(
+            id,
+            Session {
+                addr: msg.addr,
+                overloaded: msg.overloaded,
+                policy: msg.policy,
+                disconnecting: Cell::new(false),
+                user_id: msg.user_id,
+            },
+        );
+        id
+    }
+}
+
+impl Handler<Disconnect> for Broker {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
+        let rooms: Vec<String> = self
+            .rooms
+            .iter()
+            .filter(|(_, members)| members.contains(&msg.id))
+            .map(|(room, _)| room.clone())
+            .collect();
+        for room in rooms {
+            self.leave(msg.id, &room);
+        }
+        self.sessions.remove(&msg.id);
+        debug!("WS broker: session {} disconnected", msg.id);
+    }
+}
+
+impl Handler<Join> for Broker {
+    type Result = ();
+
+    fn handle(&mut self, msg: Join, _: &mut Context<Self>) {
+        let joined = ServerMessage::Joined {
+            room: msg.room.clone(),
+            session_id: msg.id,
+            user_id: self.user_id(msg.id),
+        };
+        if self.is_member(msg.id, &msg.room) {
+            return self.send(msg.id, msg.request_id, joined);
+        }
+        self.rooms.entry(msg.room.clone()).or_default().insert(msg.id);
+        self.send_room(&msg.room, joined, Some((msg.id, msg.request_id)));
+    }
+}
+
+impl Handler<Leave> for Broker {
+    type Result = ();
+
+    fn handle(&mut self, msg: Leave, _: &mut Context<Self>) {
+        if self.leave(msg.id, &msg.room) {
+            self.send(
+                msg.id,
+                msg.request_id,
+                ServerMessage::Left {
+                    room: msg.room,
+                    session_id: msg.id,
+                    user_id: self.user_id(msg.id),
+                },
+            );
+        } else {
+            self.send(
+                msg.id,
+                msg.request_id,
+                ServerMessage::Error {
+                    message: format!("Not a member of room {}", msg.room),
+                },
+            );
+        }
+    }
+}
+
+impl Handler<Broadcast> for Broker {
+    type Result = ();
+
+    fn handle(&mut self, msg: Broadcast, _: &mut Context<Self>) {
+        if msg.room == RELEASES_ROOM {
+            return self.send(
+                msg.id,
+                msg.request_id,
+                ServerMessage::Error {
+                    message: format!("Room {} is read-only", msg.room),
+                },
+            );
+        }
+        if !self.is_member(msg.id, &msg.room) {
+            return self.send(
+                msg.id,
+                msg.request_id,
+                ServerMessage::Error {
+                    message: format!("Not a member of room {}", msg.room),
+                },
+            );
+        }
+        self.send_room(
+            &msg.room,
+            ServerMessage::Message {
+                room: msg.room.clone(),
+                from: msg.id,
+                user_id: self.user_id(msg.id),
+                text: msg.text,
+            },
+            Some((msg.id, msg.request_id)),
+        );
+    }
+}
+
+impl Handler<ListMembers> for Broker {
+    type Result = ();
+
+    fn handle(&mut self, msg: ListMembers, _: &mut Context<Self>) {
+        let members = self
+            .rooms
+            .get(&msg.room)
+            .map(|members| {
+                members
+                    .iter()
+                    .map(|id| Member {
+                        session_id: *id,
+                        user_id: self.user_id(*id),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.send(
+            msg.id,
+            msg.request_id,
+            ServerMessage::Members {
+                room: msg.room,
+                members,
+            },
+        );
+    }
+}
+
+impl Handler<PublishReleases> for Broker {
+    type Result = ();
+
+    fn handle(&mut self, msg: PublishReleases, _: &mut Context<Self>) {
+        for release in msg.events {
+            self.send_room(RELEASES_ROOM, ServerMessage::ReleaseChanged { release }, None);
+        }
+    }
+}
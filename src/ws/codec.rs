@@ -0,0 +1,120 @@
+//! WebSockets messages encodings
+//!
+//! Clients choose the encoding with the `Sec-WebSocket-Protocol` header:
+//! - `json.v1`: JSON envelopes in text frames
+//! - `msgpack.v1`: MessagePack envelopes in binary frames
+//! - `cbor.v1`: CBOR envelopes in binary frames
+//!
+//! Text frames are always decoded as JSON envelopes. Without any of these subprotocols,
+//! messages are exchanged as unversioned JSON text frames.
+
+use crate::ws::protocol::{ClientMessage, Envelope, RequestId, ServerMessage, PROTOCOL_VERSION};
+use actix_web::web::Bytes;
+use actix_web_actors::ws;
+
+/// Messages encoding negotiated with the `Sec-WebSocket-Protocol` header
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Encoding {
+    /// Unversioned JSON messages, used when no subprotocol is requested
+    #[default]
+    Legacy,
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Encoding {
+    /// Returns the encoding of a subprotocol
+    pub fn from_protocol(protocol: &str) -> Option<Self> {
+        match protocol {
+            "json.v1" => Some(Self::Json),
+            "msgpack.v1" => Some(Self::MessagePack),
+            "cbor.v1" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Returns the subprotocol of the encoding
+    pub fn protocol(self) -> Option<&'static str> {
+        match self {
+            Self::Legacy => None,
+            Self::Json => Some("json.v1"),
+            Self::MessagePack => Some("msgpack.v1"),
+            Self::Cbor => Some("cbor.v1"),
+        }
+    }
+
+    /// Chooses the first supported subprotocol of a `Sec-WebSocket-Protocol` header value
+    pub fn negotiate(protocols: &str) -> Self {
+        protocols
+            .split(',')
+            .map(str::trim)
+            .find_map(Self::from_protocol)
+            .unwrap_or_default()
+    }
+
+    /// Encodes a message in a text or binary frame
+    pub fn encode(self, envelope: &Envelope<ServerMessage>) -> Result<ws::Message, String> {
+        match self {
+            Self::Legacy => serde_json::to_string(&envelope.message)
+                .map(ws::Message::Text)
+                .map_err(|e| e.to_string()),
+            Self::Json => serde_json::to_string(envelope)
+                .map(ws::Message::Text)
+                .map_err(|e| e.to_string()),
+            Self::MessagePack => rmp_serde::to_vec_named(envelope)
+                .map(|data| ws::Message::Binary(Bytes::from(data)))
+                .map_err(|e| e.to_string()),
+            Self::Cbor => serde_cbor::to_vec(envelope)
+                .map(|data| ws::Message::Binary(Bytes::from(data)))
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Decodes a text frame
+    pub fn decode_text(self, text: &str) -> Result<Envelope<ClientMessage>, DecodeError> {
+        match self {
+            Self::Legacy => serde_json::from_str(text)
+                .map(|message| Envelope::new(None, message))
+                .map_err(|e| DecodeError::new(None, format!("Invalid message: {}", e))),
+            _ => Self::check(serde_json::from_str(text).map_err(|e| e.to_string())),
+        }
+    }
+
+    /// Decodes a binary frame
+    pub fn decode_binary(self, data: &[u8]) -> Result<Envelope<ClientMessage>, DecodeError> {
+        match self {
+            Self::MessagePack => Self::check(rmp_serde::from_read_ref(data).map_err(|e| e.to_string())),
+            Self::Cbor => Self::check(serde_cbor::from_slice(data).map_err(|e| e.to_string())),
+            _ => Err(DecodeError::new(
+                None,
+                "Binary frames require the msgpack.v1 or cbor.v1 subprotocol".to_owned(),
+            )),
+        }
+    }
+
+    /// Checks the version of a decoded envelope
+    fn check(envelope: Result<Envelope<ClientMessage>, String>) -> Result<Envelope<ClientMessage>, DecodeError> {
+        match envelope {
+            Ok(envelope) if envelope.version == PROTOCOL_VERSION => Ok(envelope),
+            Ok(envelope) => Err(DecodeError::new(
+                envelope.id,
+                format!("Unsupported protocol version {}", envelope.version),
+            )),
+            Err(e) => Err(DecodeError::new(None, format!("Invalid message: {}", e))),
+        }
+    }
+}
+
+/// Error returned to the client when a frame cannot be decoded
+#[derive(Debug)]
+pub struct DecodeError {
+    pub id: Option<RequestId>,
+    pub message: String,
+}
+
+impl DecodeError {
+    fn new(id: Option<RequestId>, message: String) -> Self {
+        Self { id, message }
+    }
+}
@@ -0,0 +1,148 @@
+//! WebSockets sessions limits
+
+use actix_http::ws::Item;
+use actix_web::web::{Bytes, BytesMut};
+use actix_web_actors::ws;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Policy applied when the outbound queue of a session is full
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueuePolicy {
+    /// New messages are dropped until the client catches up
+    Drop,
+    /// The session is closed
+    Disconnect,
+}
+
+impl FromStr for QueuePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(Self::Drop),
+            "disconnect" => Ok(Self::Disconnect),
+            _ => Err(format!("invalid queue policy: {}", s)),
+        }
+    }
+}
+
+/// Limits the number of messages received per second
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Maximum number of messages per second, `0` to disable the limit
+    limit: u32,
+    count: u32,
+    window_start: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            count: 0,
+            window_start: Instant::now(),
+        }
+    }
+
+    /// Counts a new message and returns `false` if it exceeds the limit
+    pub fn check(&mut self) -> bool {
+        if self.limit == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.count = 0;
+        }
+        self.count += 1;
+        self.count <= self.limit
+    }
+}
+
+/// Error while reassembling a fragmented message, the session must be closed
+#[derive(Debug, PartialEq)]
+pub enum FragmentError {
+    TooLarge,
+    UnexpectedFrame,
+    InvalidUtf8,
+}
+
+impl FragmentError {
+    /// Close reason sent to the client
+    pub fn close_reason(&self) -> ws::CloseReason {
+        let (code, description) = match self {
+            Self::TooLarge => (ws::CloseCode::Size, "Message too large"),
+            Self::UnexpectedFrame => (ws::CloseCode::Protocol, "Unexpected continuation frame"),
+            Self::InvalidUtf8 => (ws::CloseCode::Invalid, "Invalid UTF-8 text"),
+        };
+        ws::CloseReason {
+            code,
+            description: Some(description.to_owned()),
+        }
+    }
+}
+
+/// Reassembles messages split into continuation frames
+#[derive(Debug)]
+pub struct Fragments {
+    /// Maximum size of a reassembled message (in bytes)
+    max_size: usize,
+    /// `true` for a text message, `None` if no message is being reassembled
+    text: Option<bool>,
+    buffer: BytesMut,
+}
+
+impl Fragments {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            text: None,
+            buffer: BytesMut::new(),
+        }
+    }
+
+    /// Adds a continuation frame and returns the whole message once its last frame is received
+    pub fn push(&mut self, item: Item) -> Result<Option<ws::Message>, FragmentError> {
+        match item {
+            Item::FirstText(data) => self.start(true, data).map(|_| None),
+            Item::FirstBinary(data) => self.start(false, data).map(|_| None),
+            Item::Continue(data) => self.append(data).map(|_| None),
+            Item::Last(data) => {
+                self.append(data)?;
+                self.finish().map(Some)
+            }
+        }
+    }
+
+    fn start(&mut self, text: bool, data: Bytes) -> Result<(), FragmentError> {
+        if self.text.is_some() {
+            return Err(FragmentError::UnexpectedFrame);
+        }
+        self.text = Some(text);
+        self.buffer.clear();
+        self.append(data)
+    }
+
+    fn append(&mut self, data: Bytes) -> Result<(), FragmentError> {
+        if self.text.is_none() {
+            return Err(FragmentError::UnexpectedFrame);
+        }
+        if self.buffer.len() + data.len() > self.max_size {
+            return Err(FragmentError::TooLarge);
+        }
+        self.buffer.extend_from_slice(&data);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<ws::Message, FragmentError> {
+        let data = self.buffer.split().freeze();
+        match self.text.take() {
+            Some(true) => String::from_utf8(data.to_vec())
+                .map(ws::Message::Text)
+                .map_err(|_| FragmentError::InvalidUtf8),
+            _ => Ok(ws::Message::Binary(data)),
+        }
+    }
+}
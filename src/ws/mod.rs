@@ -0,0 +1,298 @@
+//! WebSockets module
+
+pub mod broker;
+pub mod codec;
+pub mod limits;
+pub mod protocol;
+
+use crate::metrics::Metrics;
+use crate::models::auth::Claims;
+use actix::{
+    Actor, ActorContext, ActorFuture, AsyncContext, ContextFutureSpawner, Handler, StreamHandler, SystemService,
+    WrapFuture,
+};
+use actix_web_actors::ws;
+use broker::{Broadcast, Broker, Connect, Disconnect, Join, Leave, ListMembers, Overloaded, RELEASES_ROOM};
+use chrono::Utc;
+use codec::Encoding;
+use color_eyre::Result;
+use limits::{Fragments, QueuePolicy, RateLimiter};
+use protocol::{ClientMessage, Envelope, RequestId, ServerMessage, SessionId};
+use std::time::{Duration, Instant};
+
+/// WebSocket sessions settings
+#[derive(Debug, Clone)]
+pub struct WsSettings {
+    /// Interval between two pings sent by the server
+    pub heartbeat_interval: Duration,
+    /// The session is closed if nothing is received from the client during this duration
+    pub client_timeout: Duration,
+    /// Maximum size of a frame payload (in bytes)
+    pub max_frame_size: usize,
+    /// Maximum size of a message reassembled from continuation frames (in bytes)
+    pub max_message_size: usize,
+    /// Maximum number of messages waiting to be sent to the client
+    pub outbound_queue_size: usize,
+    /// Policy applied when the outbound queue is full
+    pub outbound_queue_policy: QueuePolicy,
+    /// Maximum number of messages per second received from the client, `0` to disable the limit
+    pub rate_limit: u32,
+}
+
+impl Default for WsSettings {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval: Duration::from_secs(5),
+            client_timeout: Duration::from_secs(10),
+            max_frame_size: 64 * 1024,
+            max_message_size: 1024 * 1024,
+            outbound_queue_size: 256,
+            outbound_queue_policy: QueuePolicy::Disconnect,
+            rate_limit: 20,
+        }
+    }
+}
+
+/// Define HTTP actor
+pub struct WebSocket {
+    id: SessionId,
+    /// Authenticated user, `None` for read-only sessions only receiving releases changes
+    claims: Option<Claims>,
+    /// Last time something was received from the client
+    heartbeat: Instant,
+    /// Messages encoding negotiated with the client
+    encoding: Encoding,
+    /// Message being reassembled from continuation frames
+    fragments: Fragments,
+    rate_limiter: RateLimiter,
+    settings: WsSettings,
+    metrics: Metrics,
+}
+
+impl WebSocket {
+    /// Creates a new session for an authenticated user
+    pub fn new(claims: Claims, encoding: Encoding, settings: WsSettings, metrics: Metrics) -> Self {
+        Self::build(Some(claims), encoding, settings, metrics)
+    }
+
+    /// Creates a new anonymous and read-only session subscribed to releases changes
+    pub fn releases(encoding: Encoding, settings: WsSettings, metrics: Metrics) -> Self {
+        Self::build(None, encoding, settings, metrics)
+    }
+
+    fn build(claims: Option<Claims>, encoding: Encoding, settings: WsSettings, metrics: Metrics) -> Self {
+        Self {
+            id: 0,
+            claims,
+            heartbeat: Instant::now(),
+            encoding,
+            fragments: Fragments::new(settings.max_message_size),
+            rate_limiter: RateLimiter::new(settings.rate_limit),
+            settings,
+            metrics,
+        }
+    }
+
+    /// Ping the client at each heartbeat interval and close the session after the idle timeout
+    fn heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_interval(self.settings.heartbeat_interval, |act, ctx| {
+            if Instant::now().duration_since(act.heartbeat) > act.settings.client_timeout {
+                info!("WS: session {} idle timeout, disconnecting", act.id);
+                act.metrics.ws_timeouts.inc();
+                return Self::close(ctx, ws::CloseCode::Away, "Idle timeout");
+            }
+
+            ctx.ping(b"");
+        });
+    }
+
+    /// Returns the authenticated user ID or an empty string for anonymous sessions
+    fn user_id(&self) -> String {
+        self.claims
+            .as_ref()
+            .map(|claims| claims.user_id.clone())
+            .unwrap_or_default()
+    }
+
+    /// Close the session with a policy violation when the token expires
+    fn expiration(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        let exp = match &self.claims {
+            Some(claims) => claims.exp,
+            None => return,
+        };
+        let ttl = (exp - Utc::now().timestamp()).max(0) as u64;
+
+        ctx.run_later(Duration::from_secs(ttl), |act, ctx| {
+            info!("WS: session {} token expired, disconnecting", act.id);
+            Self::close(ctx, ws::CloseCode::Policy, "Token expired");
+        });
+    }
+
+    /// Close the session
+    fn close(ctx: &mut ws::WebsocketContext<Self>, code: ws::CloseCode, description: &str) {
+        ctx.close(Some(ws::CloseReason {
+            code,
+            description: Some(description.to_owned()),
+        }));
+        ctx.stop();
+    }
+
+    /// Send a message to the client in the negotiated encoding
+    fn send(&self, ctx: &mut ws::WebsocketContext<Self>, envelope: &Envelope<ServerMessage>) {
+        match self.encoding.encode(envelope) {
+            Ok(ws::Message::Binary(data)) => ctx.binary(data),
+            Ok(ws::Message::Text(text)) => ctx.text(text),
+            Ok(_) => (),
+            Err(e) => error!("WS: failed to serialize message: {}", e),
+        }
+    }
+
+    /// Send an error to the client
+    fn send_error(&self, ctx: &mut ws::WebsocketContext<Self>, request_id: Option<RequestId>, message: String) {
+        self.send(ctx, &Envelope::new(request_id, ServerMessage::Error { message }));
+    }
+
+    /// Decode and process a text or binary message from the client
+    fn process_data(&mut self, ctx: &mut ws::WebsocketContext<Self>, msg: ws::Message) {
+        if !self.rate_limiter.check() {
+            return self.send_error(ctx, None, "Rate limit exceeded".to_owned());
+        }
+
+        let envelope = match msg {
+            ws::Message::Text(text) => self.encoding.decode_text(&text),
+            ws::Message::Binary(data) => self.encoding.decode_binary(&data),
+            _ => return,
+        };
+        match envelope {
+            Ok(envelope) => self.process_message(ctx, envelope),
+            Err(e) => self.send_error(ctx, e.id, e.message),
+        }
+    }
+
+    /// Process a decoded message from the client
+    fn process_message(&self, ctx: &mut ws::WebsocketContext<Self>, envelope: Envelope<ClientMessage>) {
+        let request_id = envelope.id;
+        if self.claims.is_none() {
+            return self.send_error(ctx, request_id, "Read-only session".to_owned());
+        }
+
+        let broker = Broker::from_registry();
+        let id = self.id;
+        match envelope.message {
+            ClientMessage::Join { room } => broker.do_send(Join { id, request_id, room }),
+            ClientMessage::Leave { room } => broker.do_send(Leave { id, request_id, room }),
+            ClientMessage::Message { room, text } => broker.do_send(Broadcast {
+                id,
+                request_id,
+                room,
+                text,
+            }),
+            ClientMessage::Members { room } => broker.do_send(ListMembers { id, request_id, room }),
+        }
+    }
+}
+
+impl Actor for WebSocket {
+    type Context = ws::WebsocketContext<Self>;
+
+    /// Register the session to the broker and start the heartbeat
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.metrics.ws_active_sessions.inc();
+        ctx.set_mailbox_capacity(self.settings.outbound_queue_size);
+        self.heartbeat(ctx);
+        self.expiration(ctx);
+
+        let addr = ctx.address();
+        let user_id = self.user_id();
+        Broker::from_registry()
+            .send(Connect {
+                addr: addr.clone().recipient(),
+                overloaded: addr.recipient(),
+                policy: self.settings.outbound_queue_policy,
+                user_id,
+            })
+            .into_actor(self)
+            .then(|res, act, ctx| {
+                match res {
+                    Ok(id) => {
+                        act.id = id;
+                        act.send(
+                            ctx,
+                            &Envelope::new(
+                                None,
+                                ServerMessage::Welcome {
+                                    session_id: id,
+                                    user_id: act.user_id(),
+                                },
+                            ),
+                        );
+                        if act.claims.is_none() {
+                            Broker::from_registry().do_send(Join {
+                                id,
+                                request_id: None,
+                                room: RELEASES_ROOM.to_owned(),
+                            });
+                        }
+                    }
+                    Err(e) => {
+                        error!("WS: failed to connect to broker: {}", e);
+                        ctx.stop();
+                    }
+                }
+                actix::fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    /// Unregister the session from the broker
+    fn stopped(&mut self, _: &mut Self::Context) {
+        self.metrics.ws_active_sessions.dec();
+        Broker::from_registry().do_send(Disconnect { id: self.id });
+    }
+}
+
+/// Handler for messages sent by the broker
+impl Handler<Envelope<ServerMessage>> for WebSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: Envelope<ServerMessage>, ctx: &mut Self::Context) {
+        self.send(ctx, &msg);
+    }
+}
+
+/// Handler for the broker notifying a full outbound queue
+impl Handler<Overloaded> for WebSocket {
+    type Result = ();
+
+    fn handle(&mut self, _: Overloaded, ctx: &mut Self::Context) {
+        warn!("WS: session {} outbound queue is full, disconnecting", self.id);
+        Self::close(ctx, ws::CloseCode::Policy, "Outbound queue full");
+    }
+}
+
+/// Handler for ws::Message message
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocket {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        debug!("WS: {:?}", msg);
+        self.heartbeat = Instant::now();
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Pong(_)) => (),
+            Ok(msg @ ws::Message::Text(_)) | Ok(msg @ ws::Message::Binary(_)) => self.process_data(ctx, msg),
+            Ok(ws::Message::Continuation(item)) => match self.fragments.push(item) {
+                Ok(Some(msg)) => self.process_data(ctx, msg),
+                Ok(None) => (),
+                Err(e) => {
+                    ctx.close(Some(e.close_reason()));
+                    ctx.stop()
+                }
+            },
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop()
+            }
+            Err(ws::ProtocolError::Overflow) => Self::close(ctx, ws::CloseCode::Size, "Frame too large"),
+            _ => ctx.stop(),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! WebSockets messages protocol
+
+use actix::Message;
+use serde::{Deserialize, Serialize};
+
+/// WebSocket session identifier
+pub type SessionId = usize;
+
+/// Correlation ID chosen by a client, copied in the response to its request
+pub type RequestId = u64;
+
+/// Current version of the messages envelope
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Versioned envelope used by negotiated subprotocols (see [`Encoding`](super::codec::Encoding))
+///
+/// ```json
+/// {"version": 1, "id": 42, "message": {"type": "join", "room": "rust"}}
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Envelope<T> {
+    pub version: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<RequestId>,
+    pub message: T,
+}
+
+impl<T> Envelope<T> {
+    /// Wraps a message in an envelope of the current version
+    pub fn new(id: Option<RequestId>, message: T) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            id,
+            message,
+        }
+    }
+}
+
+/// Messages sent to sessions by the broker
+impl Message for Envelope<ServerMessage> {
+    type Result = ();
+}
+
+/// Messages sent by clients
+///
+/// ```json
+/// {"type": "join", "room": "rust"}
+/// {"type": "leave", "room": "rust"}
+/// {"type": "message", "room": "rust", "text": "Hello!"}
+/// {"type": "members", "room": "rust"}
+/// ```
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Join { room: String },
+    Leave { room: String },
+    Message { room: String, text: String },
+    Members { room: String },
+}
+
+/// Room member
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Member {
+    pub session_id: SessionId,
+    pub user_id: String,
+}
+
+/// New release of a tracked project
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ReleaseEvent {
+    pub name: String,
+    pub repo: String,
+    pub language: String,
+    pub tag_name: String,
+    pub previous_tag_name: String,
+    pub url: String,
+    pub published_at: String,
+}
+
+/// Messages sent to clients
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Welcome {
+        session_id: SessionId,
+        user_id: String,
+    },
+    Joined {
+        room: String,
+        session_id: SessionId,
+        user_id: String,
+    },
+    Left {
+        room: String,
+        session_id: SessionId,
+        user_id: String,
+    },
+    Message {
+        room: String,
+        from: SessionId,
+        user_id: String,
+        text: String,
+    },
+    Members {
+        room: String,
+        members: Vec<Member>,
+    },
+    ReleaseChanged {
+        release: ReleaseEvent,
+    },
+    Error {
+        message: String,
+    },
+}
@@ -11,12 +11,45 @@ $(function () {
         $(this).html(duration2);
     });
 
-    $('#releases').DataTable({
-        'pageLength': 25,
-        'order': [[3, 'desc']],
-    });
-
     const expiredAt = moment($("#cacheExpiredAt").text().trim());
     const duration = moment.duration(expiredAt.diff(now)).humanize(true);
     $('#cacheExpiredAt').html(duration);
+
+    // Live releases updates
+    // ---------------------
+    const updateRelease = function (release) {
+        const row = $('tr').filter(function () {
+            return $(this).data('repo') === release.repo;
+        });
+        if (row.length === 0) {
+            return;
+        }
+
+        const link = $('<a target="_blank"></a>').attr('href', release.url).text(release.tag_name);
+        row.find('.release-tag').empty().append(link);
+
+        const publishedAt = moment(release.published_at);
+        row.find('.release-date .datetime').text(publishedAt.format('YYYY-MM-DD HH:mm'));
+        row.find('.release-date .datetime-human').text(publishedAt.fromNow());
+
+        row.addClass('table-success');
+    };
+
+    const connect = function () {
+        const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
+        const socket = new WebSocket(protocol + '//' + window.location.host + '/ws/releases');
+
+        socket.onmessage = function (event) {
+            const message = JSON.parse(event.data);
+            if (message.type === 'release_changed') {
+                updateRelease(message.release);
+            }
+        };
+
+        // Reconnect after server restart or idle timeout
+        socket.onclose = function () {
+            setTimeout(connect, 5000);
+        };
+    };
+    connect();
 });
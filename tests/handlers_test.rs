@@ -17,6 +17,19 @@ async fn test_health_check_ok() {
     assert_eq!(0, body.len());
 }
 
+#[actix_rt::test]
+async fn test_health_live_ok() {
+    let mut app =
+        test::init_service(App::new().route("/health/live", web::get().to(test_actix::handlers::health::live))).await;
+
+    let req = test::TestRequest::get().uri("/health/live").to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+
+    let body = test::read_body(resp).await;
+    assert_eq!(body, Bytes::from_static(br#"{"status":"up","components":{}}"#));
+}
+
 #[actix_rt::test]
 async fn test_hello_ok() {
     let mut app = test::init_service(App::new().service(test_actix::handlers::hello)).await;
@@ -40,3 +53,186 @@ async fn test_request_ok() {
     let body = test::read_body(resp).await;
     assert_eq!(body, Bytes::from_static(b"Test: string=toto and int=12."));
 }
+
+#[actix_rt::test]
+async fn test_big_json_stream() {
+    let mut app = test::init_service(App::new().service(test_actix::handlers::big_json_stream)).await;
+
+    // Empty stream
+    let req = test::TestRequest::get().uri("/big-json-stream/0").to_request();
+    let body = test::read_body(test::call_service(&mut app, req).await).await;
+    assert_eq!(body, Bytes::from_static(b"[]"));
+
+    let req = test::TestRequest::get().uri("/big-json-stream/3").to_request();
+    let body = test::read_body(test::call_service(&mut app, req).await).await;
+    let tasks: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(tasks.as_array().unwrap().len(), 3);
+
+    // NDJSON
+    let req = test::TestRequest::get()
+        .uri("/big-json-stream/3")
+        .header("Accept", "application/x-ndjson")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.headers().get("content-type").unwrap(), "application/x-ndjson");
+    let body = test::read_body(resp).await;
+    assert_eq!(body.split(|b| *b == b'\n').filter(|line| !line.is_empty()).count(), 3);
+
+    // CSV
+    let req = test::TestRequest::get()
+        .uri("/big-json-stream/2")
+        .header("Accept", "text/csv")
+        .to_request();
+    let body = test::read_body(test::call_service(&mut app, req).await).await;
+    let mut lines = std::str::from_utf8(&body).unwrap().lines();
+    assert_eq!(lines.next(), Some("id,name,message"));
+    assert_eq!(lines.count(), 2);
+}
+
+#[actix_rt::test]
+async fn test_error_formats() {
+    use actix_web::{http::StatusCode, HttpResponse};
+
+    let mut app = test::init_service(
+        App::new()
+            .wrap(test_actix::middlewares::errors::ErrorRenderer)
+            .route("/private", web::get().to(HttpResponse::Unauthorized))
+            .route(
+                "/number/{n}",
+                web::get().to(|n: web::Path<u32>| async move { n.to_string() }),
+            )
+            .route(
+                "/unavailable",
+                web::get().to(|| HttpResponse::ServiceUnavailable().json(serde_json::json!({"status": "down"}))),
+            )
+            .default_service(web::route().to(test_actix::handlers::errors::not_found)),
+    )
+    .await;
+
+    // Legacy format
+    let req = test::TestRequest::get().uri("/private").to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"code": 401, "error": "Unauthorized", "message": "Unauthorized"})
+    );
+
+    // Problem details
+    let req = test::TestRequest::get()
+        .uri("/private")
+        .header("Accept", "application/problem+json")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.headers().get("content-type").unwrap(), "application/problem+json");
+    let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert_eq!(body["status"], 401);
+    assert_eq!(body["title"], "Unauthorized");
+    assert_eq!(body["code"], "unauthorized");
+    assert_eq!(body["type"], "/errors/unauthorized");
+    assert_eq!(body["instance"], "/private");
+
+    // Extractor error
+    let req = test::TestRequest::get().uri("/number/abc").to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert_eq!(body["code"], 404);
+
+    // Unknown route rendered as HTML
+    let req = test::TestRequest::get()
+        .uri("/unknown")
+        .header("Accept", "text/html,application/xhtml+xml,*/*;q=0.8")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "text/html; charset=utf-8");
+    let body = test::read_body(resp).await;
+    assert!(std::str::from_utf8(&body).unwrap().contains("<h1>404 Not Found</h1>"));
+
+    // Body written by the handler is preserved
+    let req = test::TestRequest::get()
+        .uri("/unavailable")
+        .header("Accept", "application/problem+json")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "application/json");
+    let body = test::read_body(resp).await;
+    assert_eq!(body, Bytes::from_static(br#"{"status":"down"}"#));
+}
+
+#[actix_rt::test]
+async fn test_request_id() {
+    use test_actix::middlewares::{errors::ErrorRenderer, request_id::RequestId};
+
+    let mut app = test::init_service(
+        App::new()
+            .wrap(ErrorRenderer)
+            .wrap(RequestId)
+            .route("/health_check", web::get().to(test_actix::handlers::health_check))
+            .default_service(web::route().to(test_actix::handlers::errors::not_found)),
+    )
+    .await;
+
+    // Valid ID sent back
+    let req = test::TestRequest::get()
+        .uri("/health_check")
+        .header("X-Request-Id", "client-id_42")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.headers().get("x-request-id").unwrap(), "client-id_42");
+
+    // Missing, too long or invalid IDs replaced with a generated one
+    let too_long = "a".repeat(129);
+    for request_id in [None, Some(""), Some(too_long.as_str()), Some("with space"), Some("été")] {
+        let mut req = test::TestRequest::get().uri("/health_check");
+        if let Some(request_id) = request_id {
+            req = req.header("X-Request-Id", request_id);
+        }
+        let resp = test::call_service(&mut app, req.to_request()).await;
+        let generated = resp.headers().get("x-request-id").unwrap().to_str().unwrap();
+        assert_eq!(generated.len(), 36, "{:?} not replaced", request_id);
+        assert_ne!(Some(generated), request_id);
+    }
+
+    // ID in error bodies
+    let req = test::TestRequest::get()
+        .uri("/unknown")
+        .header("X-Request-Id", "client-id_42")
+        .header("Accept", "application/problem+json")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.headers().get("x-request-id").unwrap(), "client-id_42");
+    let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert_eq!(body["request_id"], "client-id_42");
+}
+
+#[actix_rt::test]
+async fn test_unauthorized() {
+    use test_actix::middlewares::{auth::Authentication, errors::ErrorRenderer, request_id::RequestId};
+
+    let mut app = test::init_service(
+        App::new().wrap(ErrorRenderer).wrap(RequestId).service(
+            web::scope("/v1")
+                .wrap(Authentication)
+                .route("/users", web::get().to(test_actix::handlers::health_check)),
+        ),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/users")
+        .header("X-Request-Id", "client-id_42")
+        .header("Accept", "application/problem+json")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "application/problem+json");
+    let body: serde_json::Value = serde_json::from_slice(&test::read_body(resp).await).unwrap();
+    assert_eq!(body["status"], 401);
+    assert_eq!(body["code"], "unauthorized");
+    assert_eq!(body["instance"], "/v1/users");
+    assert_eq!(body["request_id"], "client-id_42");
+}
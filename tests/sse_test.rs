@@ -0,0 +1,56 @@
+//! Integration tests for Server-Sent Events
+
+use actix_web::{http::StatusCode, test, web, App};
+use chrono::Utc;
+use futures::StreamExt;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde_json::json;
+use test_actix::AppState;
+
+const SECRET: &str = "mySecretKey";
+
+/// Generates a token valid for one minute
+fn token() -> String {
+    let now = Utc::now().timestamp();
+    let claims = json!({
+        "sub": "alice",
+        "exp": now + 60,
+        "iat": now,
+        "nbf": now,
+        "user_id": "alice",
+        "user_lastname": "Doe",
+        "user_firstname": "Alice",
+        "user_email": "alice.doe@test.com",
+    });
+    encode(
+        &Header::new(Algorithm::HS512),
+        &claims,
+        &EncodingKey::from_secret(SECRET.as_bytes()),
+    )
+    .unwrap()
+}
+
+#[actix_rt::test]
+async fn test_sse_stream() {
+    let srv = test::start(|| {
+        App::new()
+            .data(AppState::new(SECRET.to_owned(), "".to_owned(), "".to_owned()))
+            .route("/events", web::get().to(test_actix::handlers::events::stream))
+    });
+
+    let response = srv.get("/events").send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let mut response = srv
+        .get("/events")
+        .bearer_auth(token())
+        .header("Last-Event-ID", "1607594400000-12")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("content-type").unwrap(), "text/event-stream");
+
+    let chunk = response.next().await.unwrap().unwrap();
+    assert_eq!(chunk, "retry: 5000\n\n");
+}
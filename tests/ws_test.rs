@@ -0,0 +1,247 @@
+//! Integration tests for WebSockets
+
+use actix_http::ws::{CloseCode, Frame, Item, Message};
+use actix_web::{client::Client, test, web, App};
+use chrono::Utc;
+use futures::{SinkExt, StreamExt};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde_json::{json, Value};
+use test_actix::AppState;
+
+const SECRET: &str = "mySecretKey";
+
+/// Starts a test server with the WebSocket route
+fn start_server() -> test::TestServer {
+    test::start(|| {
+        App::new()
+            .data(AppState::new(SECRET.to_owned(), "".to_owned(), "".to_owned()))
+            .route("/ws", web::get().to(test_actix::handlers::ws::index))
+    })
+}
+
+/// Generates a token valid for `ttl` seconds
+fn token(user_id: &str, ttl: i64) -> String {
+    let now = Utc::now().timestamp();
+    let claims = json!({
+        "sub": user_id,
+        "exp": now + ttl,
+        "iat": now,
+        "nbf": now,
+        "user_id": user_id,
+        "user_lastname": "Doe",
+        "user_firstname": "John",
+        "user_email": "john.doe@test.com",
+    });
+    encode(
+        &Header::new(Algorithm::HS512),
+        &claims,
+        &EncodingKey::from_secret(SECRET.as_bytes()),
+    )
+    .unwrap()
+}
+
+/// Reads the next text frame as JSON
+async fn next_json<S>(framed: &mut S) -> Value
+where
+    S: futures::Stream<Item = Result<Frame, actix_http::ws::ProtocolError>> + Unpin,
+{
+    match framed.next().await {
+        Some(Ok(Frame::Text(text))) => serde_json::from_slice(&text).expect("invalid JSON message"),
+        frame => panic!("unexpected frame: {:?}", frame),
+    }
+}
+
+#[actix_rt::test]
+async fn test_ws_rooms() {
+    let mut srv = start_server();
+
+    let mut alice = srv.ws_at(&format!("/ws?token={}", token("alice", 60))).await.unwrap();
+    let alice_id = next_json(&mut alice).await["session_id"].clone();
+    let mut bob = srv.ws_at(&format!("/ws?token={}", token("bob", 60))).await.unwrap();
+    let bob_id = next_json(&mut bob).await["session_id"].clone();
+
+    // Join
+    alice
+        .send(Message::Text(r#"{"type": "join", "room": "rust"}"#.into()))
+        .await
+        .unwrap();
+    assert_eq!(
+        next_json(&mut alice).await,
+        json!({"type": "joined", "room": "rust", "session_id": alice_id, "user_id": "alice"})
+    );
+    bob.send(Message::Text(r#"{"type": "join", "room": "rust"}"#.into()))
+        .await
+        .unwrap();
+    assert_eq!(
+        next_json(&mut alice).await,
+        json!({"type": "joined", "room": "rust", "session_id": bob_id, "user_id": "bob"})
+    );
+    assert_eq!(
+        next_json(&mut bob).await,
+        json!({"type": "joined", "room": "rust", "session_id": bob_id, "user_id": "bob"})
+    );
+
+    // Members
+    bob.send(Message::Text(r#"{"type": "members", "room": "rust"}"#.into()))
+        .await
+        .unwrap();
+    assert_eq!(
+        next_json(&mut bob).await,
+        json!({
+            "type": "members",
+            "room": "rust",
+            "members": [
+                {"session_id": alice_id, "user_id": "alice"},
+                {"session_id": bob_id, "user_id": "bob"},
+            ]
+        })
+    );
+
+    // Broadcast
+    alice
+        .send(Message::Text(
+            r#"{"type": "message", "room": "rust", "text": "Hello"}"#.into(),
+        ))
+        .await
+        .unwrap();
+    let expected = json!({"type": "message", "room": "rust", "from": alice_id, "user_id": "alice", "text": "Hello"});
+    assert_eq!(next_json(&mut alice).await, expected);
+    assert_eq!(next_json(&mut bob).await, expected);
+
+    // Leave
+    bob.send(Message::Text(r#"{"type": "leave", "room": "rust"}"#.into()))
+        .await
+        .unwrap();
+    let expected = json!({"type": "left", "room": "rust", "session_id": bob_id, "user_id": "bob"});
+    assert_eq!(next_json(&mut alice).await, expected);
+    assert_eq!(next_json(&mut bob).await, expected);
+
+    // Not a member anymore
+    bob.send(Message::Text(
+        r#"{"type": "message", "room": "rust", "text": "Hello"}"#.into(),
+    ))
+    .await
+    .unwrap();
+    assert_eq!(next_json(&mut bob).await["type"], "error");
+}
+
+#[actix_rt::test]
+async fn test_ws_invalid_message() {
+    let mut srv = start_server();
+
+    let mut client = srv.ws_at(&format!("/ws?token={}", token("alice", 60))).await.unwrap();
+    assert_eq!(next_json(&mut client).await["type"], "welcome");
+
+    client.send(Message::Text("Hello".into())).await.unwrap();
+    assert_eq!(next_json(&mut client).await["type"], "error");
+}
+
+#[actix_rt::test]
+async fn test_ws_unauthorized() {
+    let mut srv = start_server();
+
+    assert!(srv.ws_at("/ws").await.is_err());
+    assert!(srv.ws_at("/ws?token=invalid").await.is_err());
+}
+
+#[actix_rt::test]
+async fn test_ws_token_expiration() {
+    let mut srv = start_server();
+
+    let mut client = srv.ws_at(&format!("/ws?token={}", token("alice", 1))).await.unwrap();
+    let welcome = next_json(&mut client).await;
+    assert_eq!(welcome["type"], "welcome");
+    assert_eq!(welcome["user_id"], "alice");
+
+    match client.next().await {
+        Some(Ok(Frame::Close(Some(reason)))) => assert_eq!(reason.code, CloseCode::Policy),
+        frame => panic!("unexpected frame: {:?}", frame),
+    }
+}
+
+#[actix_rt::test]
+async fn test_ws_msgpack_rpc() {
+    let srv = start_server();
+
+    let (response, mut client) = Client::new()
+        .ws(srv.url(&format!("/ws?token={}", token("alice", 60))))
+        .protocols(["msgpack.v1"])
+        .connect()
+        .await
+        .unwrap();
+    assert_eq!(response.headers().get("sec-websocket-protocol").unwrap(), "msgpack.v1");
+
+    let next_msgpack = |frame: Option<Result<Frame, actix_http::ws::ProtocolError>>| -> Value {
+        match frame {
+            Some(Ok(Frame::Binary(data))) => rmp_serde::from_read_ref(&data).expect("invalid MessagePack message"),
+            frame => panic!("unexpected frame: {:?}", frame),
+        }
+    };
+
+    let welcome = next_msgpack(client.next().await);
+    assert_eq!(welcome["version"], 1);
+    assert_eq!(welcome["message"]["type"], "welcome");
+
+    // Request and response share the same correlation ID
+    let request = json!({"version": 1, "id": 7, "message": {"type": "join", "room": "rust"}});
+    client
+        .send(Message::Binary(rmp_serde::to_vec_named(&request).unwrap().into()))
+        .await
+        .unwrap();
+    let response = next_msgpack(client.next().await);
+    assert_eq!(response["id"], 7);
+    assert_eq!(response["message"]["type"], "joined");
+
+    // Unsupported version
+    let request = json!({"version": 2, "id": 8, "message": {"type": "members", "room": "rust"}});
+    client
+        .send(Message::Binary(rmp_serde::to_vec_named(&request).unwrap().into()))
+        .await
+        .unwrap();
+    let response = next_msgpack(client.next().await);
+    assert_eq!(response["id"], 8);
+    assert_eq!(response["message"]["type"], "error");
+}
+
+#[actix_rt::test]
+async fn test_ws_limits() {
+    let mut srv = start_server();
+
+    let mut client = srv.ws_at(&format!("/ws?token={}", token("alice", 60))).await.unwrap();
+    assert_eq!(next_json(&mut client).await["type"], "welcome");
+
+    // Continuation frames are reassembled
+    client
+        .send(Message::Continuation(Item::FirstText(r#"{"type": "join", "#.into())))
+        .await
+        .unwrap();
+    client
+        .send(Message::Continuation(Item::Last(r#""room": "rust"}"#.into())))
+        .await
+        .unwrap();
+    assert_eq!(next_json(&mut client).await["type"], "joined");
+
+    // Rate limit (20 messages per second by default, including the join message)
+    for _ in 0..20 {
+        client
+            .send(Message::Text(r#"{"type": "members", "room": "rust"}"#.into()))
+            .await
+            .unwrap();
+    }
+    // The error is sent by the session before the broker answers
+    let mut errors = Vec::new();
+    for _ in 0..20 {
+        let message = next_json(&mut client).await;
+        if message["type"] != "members" {
+            errors.push(message);
+        }
+    }
+    assert_eq!(errors, vec![json!({"type": "error", "message": "Rate limit exceeded"})]);
+
+    // Frame larger than 64 KiB
+    client.send(Message::Text("a".repeat(65 * 1024))).await.unwrap();
+    match client.next().await {
+        Some(Ok(Frame::Close(Some(reason)))) => assert_eq!(reason.code, CloseCode::Size),
+        frame => panic!("unexpected frame: {:?}", frame),
+    }
+}